@@ -0,0 +1,80 @@
+// Lookup throughput over 1M random `u64` keys. Run with `cargo bench`.
+//
+// Inserting one key at a time lays the arena out in the order the nodes were
+// created, while collecting sorts first and lays it out in key order, so the
+// same keys are timed both ways.
+//
+// It also times a counting loop over heavily repeated keys, comparing
+// `get_or_insert` against the `entry(..).or_insert(..)` pattern.
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use critbit::CritBit;
+
+const KEYS: usize = 1_000_000;
+const ROUNDS: usize = 5;
+
+fn xorshift(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+fn lookups_per_sec(tree: &CritBit<u64, u64>, keys: &[u64]) -> f64 {
+    let mut best = f64::INFINITY;
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        let mut found = 0usize;
+        for k in keys {
+            found += black_box(tree.get(k)).is_some() as usize;
+        }
+        assert_eq!(found, tree.len());
+        best = best.min(start.elapsed().as_secs_f64());
+    }
+    keys.len() as f64 / best
+}
+
+fn main() {
+    let mut state = 0x2545_f491_4f6c_dd1d;
+    let keys: Vec<u64> = (0..KEYS).map(|_| xorshift(&mut state)).collect();
+
+    let start = Instant::now();
+    let mut inserted = CritBit::new();
+    for &k in &keys {
+        inserted.insert(k, k);
+    }
+    println!("insert: {:?} for {} keys", start.elapsed(), inserted.len());
+    println!(
+        "lookup, inserted one by one: {:.2} Mlookups/s",
+        lookups_per_sec(&inserted, &keys) / 1e6
+    );
+
+    let start = Instant::now();
+    let tree: CritBit<u64, u64> = keys.iter().map(|&k| (k, k)).collect();
+    println!("collect: {:?} for {} keys", start.elapsed(), tree.len());
+    println!(
+        "lookup, collected: {:.2} Mlookups/s",
+        lookups_per_sec(&tree, &keys) / 1e6
+    );
+
+    let repeated: Vec<u64> = keys.iter().map(|k| k % 65_536).collect();
+    let start = Instant::now();
+    let mut counts = CritBit::new();
+    for &k in &repeated {
+        *counts.entry(k).or_insert(0u32) += 1;
+    }
+    println!("count via entry: {:?}", start.elapsed());
+    let start = Instant::now();
+    let mut counts = CritBit::new();
+    for &k in &repeated {
+        *counts.get_or_insert(k, 0u32) += 1;
+    }
+    println!("count via get_or_insert: {:?}", start.elapsed());
+    black_box(counts);
+
+    let start = Instant::now();
+    let sum = tree.values().fold(0u64, |acc, v| acc.wrapping_add(*v));
+    println!("iterate: {:?} (checksum {sum:x})", start.elapsed());
+}
@@ -0,0 +1,357 @@
+// Byte-string keys have no fixed width, so every walk here is a loop rather
+// than recursion: a tree over long shared prefixes can be very deep.
+//
+// Bit positions count nine bits per byte: a "present" bit followed by the
+// byte's eight data bits, most significant first. Bytes past the end of a key
+// read as zero, so shorter keys behave as if zero-extended, while the present
+// bit still tells `b"app"` apart from `b"app\0"` and sorts it first.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+pub struct CritBitBytes<V> {
+    root: Option<ByteNode<V>>,
+    len: usize,
+}
+
+enum ByteNode<V> {
+    Leaf(Box<[u8]>, V),
+    Internal(InternalByteNode<V>),
+}
+
+struct InternalByteNode<V> {
+    left: Option<Box<ByteNode<V>>>,
+    right: Option<Box<ByteNode<V>>>,
+    crit: usize,
+}
+
+#[inline(always)]
+fn byte_bit_at(key: &[u8], pos: usize) -> bool {
+    match key.get(pos / 9) {
+        None => false,
+        Some(_) if pos.is_multiple_of(9) => true,
+        Some(byte) => (byte << (pos % 9 - 1)) & 0x80 != 0,
+    }
+}
+
+// The first position at which `a` and `b` differ, or `None` if they're equal.
+fn byte_crit(a: &[u8], b: &[u8]) -> Option<usize> {
+    for i in 0..a.len().max(b.len()) {
+        match (a.get(i), b.get(i)) {
+            (Some(x), Some(y)) if x == y => {}
+            (Some(x), Some(y)) => return Some(i * 9 + 1 + (x ^ y).leading_zeros() as usize),
+            _ => return Some(i * 9),
+        }
+    }
+    None
+}
+
+impl<V> Default for CritBitBytes<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> Drop for CritBitBytes<V> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<V> CritBitBytes<V> {
+    pub const fn new() -> CritBitBytes<V> {
+        CritBitBytes { root: None, len: 0 }
+    }
+
+    pub fn clear(&mut self) {
+        let mut work = Vec::new();
+        if let Some(ByteNode::Internal(InternalByteNode { left, right, .. })) = self.root.take() {
+            work.extend(left);
+            work.extend(right);
+        }
+        while let Some(mut node) = work.pop() {
+            if let ByteNode::Internal(InternalByteNode {
+                ref mut left,
+                ref mut right,
+                ..
+            }) = *node
+            {
+                work.extend(left.take());
+                work.extend(right.take());
+            }
+        }
+        self.len = 0;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&V> {
+        match *self.root.as_ref()?.best_match(key) {
+            ByteNode::Leaf(ref k, ref v) if **k == *key => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut V> {
+        match *self.root.as_mut()?.best_match_mut(key) {
+            ByteNode::Leaf(ref k, ref mut v) if **k == *key => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn insert(&mut self, key: &[u8], value: V) -> Option<V> {
+        let Some(ref mut root) = self.root else {
+            self.root = Some(ByteNode::Leaf(key.into(), value));
+            self.len = 1;
+            return None;
+        };
+        let crit = match *root.best_match(key) {
+            ByteNode::Leaf(ref k, _) => byte_crit(k, key),
+            ByteNode::Internal(..) => unreachable!("Best matches are always leaves..."),
+        };
+        let Some(crit) = crit else {
+            if let ByteNode::Leaf(_, ref mut v) = *root.best_match_mut(key) {
+                return Some(core::mem::replace(v, value));
+            }
+            unreachable!("Best matches are always leaves...")
+        };
+
+        let mut node = root;
+        while let ByteNode::Internal(InternalByteNode { crit: c, .. }) = *node {
+            if c > crit {
+                break;
+            }
+            node = node.child_mut(byte_bit_at(key, c));
+        }
+        let old = Box::new(core::mem::replace(
+            node,
+            ByteNode::Internal(InternalByteNode {
+                left: None,
+                right: None,
+                crit,
+            }),
+        ));
+        let new = Box::new(ByteNode::Leaf(key.into(), value));
+        if let ByteNode::Internal(InternalByteNode {
+            ref mut left,
+            ref mut right,
+            ..
+        }) = *node
+        {
+            if byte_bit_at(key, crit) {
+                (*left, *right) = (Some(old), Some(new));
+            } else {
+                (*left, *right) = (Some(new), Some(old));
+            }
+        }
+        self.len += 1;
+        None
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<V> {
+        let mut node = match self.root {
+            Some(ByteNode::Leaf(ref k, _)) if **k == *key => {
+                self.len = 0;
+                return match self.root.take() {
+                    Some(ByteNode::Leaf(_, v)) => Some(v),
+                    _ => unreachable!("We just checked that this was a leaf..."),
+                };
+            }
+            Some(ref mut node) => node,
+            None => return None,
+        };
+        loop {
+            let (side, found) = match *node {
+                ByteNode::Leaf(..) => return None,
+                ByteNode::Internal(InternalByteNode {
+                    ref left,
+                    ref right,
+                    crit,
+                }) => {
+                    let side = byte_bit_at(key, crit);
+                    match if side { right } else { left }.as_deref() {
+                        Some(ByteNode::Leaf(k, _)) => (side, Some(**k == *key)),
+                        Some(ByteNode::Internal(..)) => (side, None),
+                        None => unreachable!(
+                            "Internal nodes should always have both branches filled, what happened?"
+                        ),
+                    }
+                }
+            };
+            match found {
+                Some(false) => return None,
+                Some(true) => break,
+                None => node = node.child_mut(side),
+            }
+        }
+
+        let ByteNode::Internal(InternalByteNode {
+            ref mut left,
+            ref mut right,
+            crit,
+        }) = *node
+        else {
+            unreachable!("We just checked that this was an internal node...")
+        };
+        let (kid, sibling) = if byte_bit_at(key, crit) {
+            (right.take(), left.take())
+        } else {
+            (left.take(), right.take())
+        };
+        match (kid.map(|kid| *kid), sibling) {
+            (Some(ByteNode::Leaf(_, v)), Some(sibling)) => {
+                *node = *sibling;
+                self.len -= 1;
+                Some(v)
+            }
+            _ => unreachable!(
+                "Internal nodes should always have both branches filled, what happened?"
+            ),
+        }
+    }
+}
+
+impl<V> ByteNode<V> {
+    fn best_match(&self, key: &[u8]) -> &ByteNode<V> {
+        let mut node = self;
+        while let ByteNode::Internal(InternalByteNode {
+            ref left,
+            ref right,
+            crit,
+        }) = *node
+        {
+            node = match if byte_bit_at(key, crit) { right } else { left } {
+                Some(kid) => kid,
+                None => unreachable!(
+                    "Internal nodes should always have both branches filled, what happened?"
+                ),
+            };
+        }
+        node
+    }
+
+    fn best_match_mut(&mut self, key: &[u8]) -> &mut ByteNode<V> {
+        let mut node = self;
+        while let ByteNode::Internal(InternalByteNode { crit, .. }) = *node {
+            node = node.child_mut(byte_bit_at(key, crit));
+        }
+        node
+    }
+
+    fn child_mut(&mut self, right: bool) -> &mut ByteNode<V> {
+        match *self {
+            ByteNode::Internal(InternalByteNode {
+                left: Some(ref mut kid),
+                ..
+            }) if !right => kid,
+            ByteNode::Internal(InternalByteNode {
+                right: Some(ref mut kid),
+                ..
+            }) if right => kid,
+            _ => unreachable!(
+                "Internal nodes should always have both branches filled, what happened?"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::CritBitBytes;
+    use crate::bytes::{byte_bit_at, byte_crit};
+
+    #[test]
+    fn verify_byte_bit_at() {
+        assert!(byte_bit_at(b"\x00", 0));
+        assert!(!byte_bit_at(b"\x00", 9));
+        assert!(byte_bit_at(b"\x80", 1));
+        assert!(!byte_bit_at(b"\x80", 2));
+        assert!(byte_bit_at(b"\x01", 8));
+        assert!(!byte_bit_at(b"", 0));
+        assert!(!byte_bit_at(b"", 1));
+    }
+
+    #[test]
+    fn verify_byte_crit() {
+        assert_eq!(byte_crit(b"app", b"app"), None);
+        assert_eq!(byte_crit(b"app", b"apple"), Some(27));
+        assert_eq!(byte_crit(b"app", b"app\0"), Some(27));
+        assert_eq!(byte_crit(b"\x80", b"\x00"), Some(1));
+        assert_eq!(byte_crit(b"", b""), None);
+    }
+
+    #[test]
+    fn strings() {
+        let mut t: CritBitBytes<u32> = CritBitBytes::new();
+        assert_eq!(t.insert(b"apple", 1), None);
+        assert_eq!(t.insert(b"app", 2), None);
+        assert_eq!(t.insert(b"apply", 3), None);
+        assert_eq!(t.insert(b"app\0", 4), None);
+        assert_eq!(t.insert(b"", 5), None);
+        assert_eq!(t.len(), 5);
+
+        assert_eq!(t.get(b"apple"), Some(&1));
+        assert_eq!(t.get(b"app"), Some(&2));
+        assert_eq!(t.get(b"apply"), Some(&3));
+        assert_eq!(t.get(b"app\0"), Some(&4));
+        assert_eq!(t.get(b""), Some(&5));
+        assert_eq!(t.get(b"ap"), None);
+        assert_eq!(t.get(b"apples"), None);
+
+        assert_eq!(t.insert(b"app", 20), Some(2));
+        *t.get_mut(b"apply").unwrap() += 30;
+        assert_eq!(t.get(b"app"), Some(&20));
+        assert_eq!(t.get(b"apply"), Some(&33));
+        assert_eq!(t.len(), 5);
+
+        assert_eq!(t.remove(b"app"), Some(20));
+        assert_eq!(t.remove(b"app"), None);
+        assert_eq!(t.remove(b"ap"), None);
+        assert_eq!(t.get(b"app"), None);
+        assert_eq!(t.get(b"apple"), Some(&1));
+        assert_eq!(t.get(b"app\0"), Some(&4));
+        assert_eq!(t.len(), 4);
+
+        for k in [&b"apple"[..], b"apply", b"app\0", b""] {
+            assert!(t.remove(k).is_some());
+        }
+        assert!(t.is_empty());
+        assert_eq!(t.len(), 0);
+    }
+
+    #[test]
+    fn string_keys_owned() {
+        let words = ["banana", "band", "ban", "bandana", "b", "apple"];
+        let mut t: CritBitBytes<String> = CritBitBytes::new();
+        for w in words.iter() {
+            t.insert(w.as_bytes(), w.to_uppercase());
+        }
+        for w in words.iter() {
+            assert_eq!(t.get(w.as_bytes()), Some(&w.to_uppercase()));
+        }
+        assert!(!t.contains_key("bandanas".as_bytes()));
+    }
+
+    #[test]
+    fn deep_drop() {
+        let mut t: CritBitBytes<()> = CritBitBytes::new();
+        let mut key = Vec::new();
+        for _ in 0..4_000 {
+            t.insert(&key, ());
+            key.push(0u8);
+        }
+        assert_eq!(t.len(), 4_000);
+        assert!(t.contains_key(&key[..2_000]));
+        drop(t);
+    }
+}
@@ -0,0 +1,52 @@
+use num::PrimInt;
+
+use crate::CritBit;
+
+/// A position in the tree that can step through it in key order, lending out
+/// each value mutably as it goes. Besides the entries there's one more place
+/// to stand, off the end of the tree: stepping forward from it wraps round to
+/// the smallest entry and stepping back wraps round to the largest.
+pub struct CursorMut<'a, K, V>
+where
+    K: PrimInt,
+{
+    tree: &'a mut CritBit<K, V>,
+    // The leaf the cursor is on, or `None` when it's off the end.
+    at: Option<u32>,
+}
+
+impl<'a, K: PrimInt, V> CursorMut<'a, K, V> {
+    pub(crate) fn lower_bound(tree: &'a mut CritBit<K, V>, key: &K) -> CursorMut<'a, K, V> {
+        let at = tree.root().and_then(|root| {
+            let located = tree.locate(root, key);
+            let at = match located.above {
+                None | Some(false) => located.subtree,
+                Some(true) => located.upper?,
+            };
+            Some(tree.edge(at, false))
+        });
+        CursorMut { tree, at }
+    }
+
+    pub fn current(&mut self) -> Option<(&K, &mut V)> {
+        Some(self.tree.leaf_mut(self.at?))
+    }
+
+    pub fn move_next(&mut self) {
+        self.step(true);
+    }
+
+    pub fn move_prev(&mut self) {
+        self.step(false);
+    }
+
+    fn step(&mut self, forward: bool) {
+        let Some(root) = self.tree.root() else {
+            return;
+        };
+        self.at = match self.at {
+            None => Some(self.tree.edge(root, !forward)),
+            Some(at) => self.tree.neighbour(root, self.tree.leaf(at).0, forward),
+        };
+    }
+}
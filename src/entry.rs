@@ -0,0 +1,146 @@
+use core::fmt::{self, Debug};
+
+use num::PrimInt;
+
+use crate::CritBit;
+
+pub enum Entry<'a, K, V>
+where
+    K: PrimInt,
+{
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+pub struct OccupiedEntry<'a, K, V>
+where
+    K: PrimInt,
+{
+    pub(crate) tree: &'a mut CritBit<K, V>,
+    pub(crate) key: K,
+}
+
+pub struct VacantEntry<'a, K, V>
+where
+    K: PrimInt,
+{
+    pub(crate) tree: &'a mut CritBit<K, V>,
+    pub(crate) key: K,
+}
+
+/// Returned by `CritBit::try_insert` when the key is already present. The
+/// entry points at the value that's stored, and `value` is the one that wasn't.
+pub struct OccupiedError<'a, K, V>
+where
+    K: PrimInt,
+{
+    pub entry: OccupiedEntry<'a, K, V>,
+    pub value: V,
+}
+
+impl<'a, K: PrimInt, V> Entry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        match *self {
+            Entry::Occupied(ref e) => e.key(),
+            Entry::Vacant(ref e) => e.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(default),
+        }
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(default()),
+        }
+    }
+
+    pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => {
+                let value = default(e.key());
+                e.insert(value)
+            }
+        }
+    }
+
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut e) => {
+                f(e.get_mut());
+                Entry::Occupied(e)
+            }
+            Entry::Vacant(e) => Entry::Vacant(e),
+        }
+    }
+}
+
+impl<'a, K: PrimInt, V> OccupiedEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn get(&self) -> &V {
+        self.tree
+            .get(&self.key)
+            .expect("Occupied entries always point at a present key")
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        self.tree
+            .get_mut(&self.key)
+            .expect("Occupied entries always point at a present key")
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        self.tree
+            .get_mut(&self.key)
+            .expect("Occupied entries always point at a present key")
+    }
+
+    pub fn insert(&mut self, value: V) -> V {
+        core::mem::replace(self.get_mut(), value)
+    }
+
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    pub fn remove_entry(self) -> (K, V) {
+        let value = self
+            .tree
+            .remove(&self.key)
+            .expect("Occupied entries always point at a present key");
+        (self.key, value)
+    }
+}
+
+impl<'a, K: PrimInt, V> VacantEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.tree.insert_slot(self.key, value).1
+    }
+}
+
+impl<K: PrimInt + Debug, V: Debug> Debug for OccupiedError<'_, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OccupiedError")
+            .field("key", self.entry.key())
+            .field("old_value", self.entry.get())
+            .field("new_value", &self.value)
+            .finish()
+    }
+}
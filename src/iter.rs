@@ -0,0 +1,591 @@
+use num::PrimInt;
+
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::iter::Peekable;
+use core::marker::PhantomData;
+use core::ops::Bound;
+use core::ptr::NonNull;
+
+use crate::{CritBit, CritBitNode, InternalCritBitNode, bit_at, common_prefix_len};
+
+pub struct Iter<'a, K, V>
+where
+    K: PrimInt,
+{
+    nodes: &'a [CritBitNode<K, V>],
+    stack: Vec<u32>,
+    // Walks right-first from the other end. It's only built on the first
+    // `next_back`, from whatever `stack` still covers, and `remaining` keeps
+    // the two walks from handing out the same leaf once they meet.
+    back: Vec<u32>,
+    remaining: usize,
+}
+
+impl<'a, K: PrimInt, V> Iter<'a, K, V> {
+    pub(crate) fn new(nodes: &'a [CritBitNode<K, V>], root: Option<u32>) -> Iter<'a, K, V> {
+        Iter::from_stack(nodes, root.into_iter().collect())
+    }
+
+    fn from_stack(nodes: &'a [CritBitNode<K, V>], stack: Vec<u32>) -> Iter<'a, K, V> {
+        let remaining = stack.iter().map(|&at| nodes[at as usize].len()).sum();
+        Iter {
+            nodes,
+            stack,
+            back: Vec::new(),
+            remaining,
+        }
+    }
+
+    pub(crate) fn finish(&mut self) {
+        self.remaining = 0;
+    }
+
+    pub(crate) fn seek(tree: &'a CritBit<K, V>, key: &K) -> Iter<'a, K, V> {
+        Iter::from_stack(&tree.nodes, seek_stack(tree, key))
+    }
+}
+
+// Builds the traversal stack that an in-order walk would have just before
+// yielding the first key `>= key`, without visiting any smaller leaves.
+fn seek_stack<K: PrimInt, V>(tree: &CritBit<K, V>, key: &K) -> Vec<u32> {
+    let mut stack = Vec::new();
+    if let Some(root) = tree.root() {
+        let crit = common_prefix_len(tree.leaf(tree.best_match(root, key)).0, key);
+        let mut at = root;
+        loop {
+            match tree.nodes[at as usize] {
+                CritBitNode::Internal(InternalCritBitNode {
+                    left,
+                    right,
+                    crit: c,
+                    ..
+                }) if c < crit => {
+                    if bit_at(key, &c) {
+                        at = right;
+                    } else {
+                        stack.push(right);
+                        at = left;
+                    }
+                }
+                // Every key below `at` agrees with `key` above `crit`, so
+                // either `key` is present or they all sort on one side of it.
+                _ => {
+                    if crit == CritBit::<K, V>::key_bits() || !bit_at(key, &crit) {
+                        stack.push(at);
+                    }
+                    break;
+                }
+            }
+        }
+    }
+    stack
+}
+
+impl<'a, K: PrimInt, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let nodes = self.nodes;
+        while let Some(at) = self.stack.pop() {
+            match nodes[at as usize] {
+                CritBitNode::Leaf(ref k, ref v) => {
+                    self.remaining -= 1;
+                    return Some((k, v));
+                }
+                CritBitNode::Internal(InternalCritBitNode { left, right, .. }) => {
+                    self.stack.push(right);
+                    self.stack.push(left);
+                }
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, K: PrimInt, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+impl<'a, K: PrimInt, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        if self.back.is_empty() {
+            self.back.extend(self.stack.iter().rev());
+        }
+        let nodes = self.nodes;
+        while let Some(at) = self.back.pop() {
+            match nodes[at as usize] {
+                CritBitNode::Leaf(ref k, ref v) => {
+                    self.remaining -= 1;
+                    return Some((k, v));
+                }
+                CritBitNode::Internal(InternalCritBitNode { left, right, .. }) => {
+                    self.back.push(left);
+                    self.back.push(right);
+                }
+            }
+        }
+        None
+    }
+}
+
+pub struct IterMut<'a, K, V>
+where
+    K: PrimInt,
+{
+    // The arena, held as a raw pointer rather than a `&mut` slice so that each
+    // leaf's value can be lent out on its own while the walk keeps reading the
+    // internal nodes around it.
+    nodes: NonNull<CritBitNode<K, V>>,
+    stack: Vec<u32>,
+    remaining: usize,
+    marker: PhantomData<&'a mut CritBitNode<K, V>>,
+}
+
+// SAFETY: `IterMut` hands out `&K` and `&mut V` and nothing else, just as a
+// `&mut [CritBitNode<K, V>]` would.
+unsafe impl<'a, K: PrimInt + Sync, V: Send> Send for IterMut<'a, K, V> {}
+unsafe impl<'a, K: PrimInt + Sync, V: Sync> Sync for IterMut<'a, K, V> {}
+
+impl<'a, K: PrimInt, V> IterMut<'a, K, V> {
+    pub(crate) fn new(tree: &'a mut CritBit<K, V>) -> IterMut<'a, K, V> {
+        let stack = tree.root().into_iter().collect();
+        IterMut::from_stack(tree, stack)
+    }
+
+    // Like `Iter::seek`: starts at the first key `>= key`.
+    pub(crate) fn seek(tree: &'a mut CritBit<K, V>, key: &K) -> IterMut<'a, K, V> {
+        let stack = seek_stack(tree, key);
+        IterMut::from_stack(tree, stack)
+    }
+
+    fn from_stack(tree: &'a mut CritBit<K, V>, stack: Vec<u32>) -> IterMut<'a, K, V> {
+        IterMut {
+            remaining: stack.iter().map(|&at| tree.size(at)).sum(),
+            stack,
+            nodes: NonNull::from(tree.nodes.as_mut_slice()).cast(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, K: PrimInt, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(at) = self.stack.pop() {
+            // SAFETY: `at` came from the root or an internal node, so it's in
+            // bounds of the arena, which stays mutably borrowed for `'a`. Each
+            // node hangs off exactly one parent, so each leaf is reached once
+            // and the borrows handed out never overlap one another, nor the
+            // internal nodes the walk goes on reading.
+            match unsafe { &mut *self.nodes.as_ptr().add(at as usize) } {
+                CritBitNode::Leaf(k, v) => {
+                    self.remaining -= 1;
+                    return Some((&*k, v));
+                }
+                CritBitNode::Internal(InternalCritBitNode { left, right, .. }) => {
+                    self.stack.push(*right);
+                    self.stack.push(*left);
+                }
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, K: PrimInt, V> ExactSizeIterator for IterMut<'a, K, V> {}
+
+pub struct IntoIter<K, V>
+where
+    K: PrimInt,
+{
+    nodes: Vec<Option<CritBitNode<K, V>>>,
+    stack: Vec<u32>,
+    remaining: usize,
+}
+
+impl<K: PrimInt, V> IntoIter<K, V> {
+    pub(crate) fn new(tree: CritBit<K, V>) -> IntoIter<K, V> {
+        IntoIter {
+            stack: tree.root().into_iter().collect(),
+            remaining: tree.len(),
+            nodes: tree.nodes.into_iter().map(Some).collect(),
+        }
+    }
+}
+
+impl<K: PrimInt, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(at) = self.stack.pop() {
+            match self.nodes[at as usize].take() {
+                Some(CritBitNode::Leaf(k, v)) => {
+                    self.remaining -= 1;
+                    return Some((k, v));
+                }
+                Some(CritBitNode::Internal(InternalCritBitNode { left, right, .. })) => {
+                    self.stack.push(right);
+                    self.stack.push(left);
+                }
+                None => unreachable!("Every node hangs off exactly one parent..."),
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K: PrimInt, V> ExactSizeIterator for IntoIter<K, V> {}
+
+pub struct IntoKeys<K, V>(pub(crate) IntoIter<K, V>)
+where
+    K: PrimInt;
+
+impl<K: PrimInt, V> Iterator for IntoKeys<K, V> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<K: PrimInt, V> ExactSizeIterator for IntoKeys<K, V> {}
+
+pub struct IntoValues<K, V>(pub(crate) IntoIter<K, V>)
+where
+    K: PrimInt;
+
+impl<K: PrimInt, V> Iterator for IntoValues<K, V> {
+    type Item = V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(_, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<K: PrimInt, V> ExactSizeIterator for IntoValues<K, V> {}
+
+pub struct Keys<'a, K, V>(pub(crate) Iter<'a, K, V>)
+where
+    K: PrimInt;
+
+impl<'a, K: PrimInt, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a, K: PrimInt, V> ExactSizeIterator for Keys<'a, K, V> {}
+
+impl<'a, K: PrimInt, V> DoubleEndedIterator for Keys<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|(k, _)| k)
+    }
+}
+
+pub struct Values<'a, K, V>(pub(crate) Iter<'a, K, V>)
+where
+    K: PrimInt;
+
+impl<'a, K: PrimInt, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(_, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a, K: PrimInt, V> ExactSizeIterator for Values<'a, K, V> {}
+
+impl<'a, K: PrimInt, V> DoubleEndedIterator for Values<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|(_, v)| v)
+    }
+}
+
+pub struct ValuesMut<'a, K, V>(pub(crate) IterMut<'a, K, V>)
+where
+    K: PrimInt;
+
+impl<'a, K: PrimInt, V> Iterator for ValuesMut<'a, K, V> {
+    type Item = &'a mut V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(_, v)| v)
+    }
+}
+
+pub struct Range<'a, K, V>
+where
+    K: PrimInt,
+{
+    iter: Iter<'a, K, V>,
+    start: Bound<K>,
+    end: Bound<K>,
+}
+
+impl<'a, K: PrimInt, V> Range<'a, K, V> {
+    pub(crate) fn new(tree: &'a CritBit<K, V>, start: Bound<K>, end: Bound<K>) -> Range<'a, K, V> {
+        let iter = match start {
+            Bound::Included(ref k) | Bound::Excluded(ref k) => Iter::seek(tree, k),
+            Bound::Unbounded => tree.iter(),
+        };
+        Range { iter, start, end }
+    }
+}
+
+impl<'a, K: PrimInt, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((k, v)) = self.iter.next() {
+            if let Bound::Excluded(ref start) = self.start
+                && *k == *start
+            {
+                continue;
+            }
+            let below_end = match self.end {
+                Bound::Included(ref end) => *k <= *end,
+                Bound::Excluded(ref end) => *k < *end,
+                Bound::Unbounded => true,
+            };
+            if !below_end {
+                self.iter.finish();
+                return None;
+            }
+            return Some((k, v));
+        }
+        None
+    }
+}
+
+pub struct PrefixIter<'a, K, V>(pub(crate) Iter<'a, K, V>)
+where
+    K: PrimInt;
+
+impl<'a, K: PrimInt, V> Iterator for PrefixIter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+/// Moves the entries out in key order. The tree is emptied up front, so it
+/// stays empty however much of the `Drain` is consumed; whatever is left is
+/// dropped along with the `Drain`, and the arena keeps its allocation for the
+/// tree to reuse, as with `clear`.
+pub struct Drain<'a, K, V>
+where
+    K: PrimInt,
+{
+    // Already emptied: the nodes being walked sit in its spare capacity.
+    nodes: &'a mut Vec<CritBitNode<K, V>>,
+    stack: Vec<u32>,
+    remaining: usize,
+}
+
+impl<'a, K: PrimInt, V> Drain<'a, K, V> {
+    pub(crate) fn new(tree: &'a mut CritBit<K, V>) -> Drain<'a, K, V> {
+        let stack = tree.root().into_iter().collect();
+        let remaining = tree.len();
+        // SAFETY: the nodes stay where they are, and `next` moves each one out
+        // at most once. Shortening the `Vec` first means a leaked `Drain`
+        // leaves an empty tree behind, leaking entries rather than exposing
+        // ones that were moved out.
+        unsafe { tree.nodes.set_len(0) };
+        Drain {
+            nodes: &mut tree.nodes,
+            stack,
+            remaining,
+        }
+    }
+}
+
+impl<'a, K: PrimInt, V> Iterator for Drain<'a, K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(at) = self.stack.pop() {
+            // SAFETY: `at` came from the root or an internal node, so it's
+            // below the arena's old length. Each node hangs off exactly one
+            // parent, so it's popped, and moved out, only once.
+            match unsafe { core::ptr::read(self.nodes.as_ptr().add(at as usize)) } {
+                CritBitNode::Leaf(k, v) => {
+                    self.remaining -= 1;
+                    return Some((k, v));
+                }
+                CritBitNode::Internal(InternalCritBitNode { left, right, .. }) => {
+                    self.stack.push(right);
+                    self.stack.push(left);
+                }
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, K: PrimInt, V> ExactSizeIterator for Drain<'a, K, V> {}
+
+impl<'a, K: PrimInt, V> Drop for Drain<'a, K, V> {
+    fn drop(&mut self) {
+        // The nodes are already out of the `Vec`, so unless the values need
+        // dropping there's nothing left to do.
+        if core::mem::needs_drop::<V>() {
+            self.for_each(drop);
+        }
+    }
+}
+
+/// Keys in either tree, ascending. Like `Intersection` and `Difference`, it
+/// merges two ascending key streams in step, so it's linear in the two trees'
+/// sizes and never buffers anything.
+pub struct Union<'a, K, V>
+where
+    K: PrimInt,
+{
+    a: Peekable<Keys<'a, K, V>>,
+    b: Peekable<Keys<'a, K, V>>,
+}
+
+impl<'a, K: PrimInt, V> Union<'a, K, V> {
+    pub(crate) fn new(a: Keys<'a, K, V>, b: Keys<'a, K, V>) -> Union<'a, K, V> {
+        Union {
+            a: a.peekable(),
+            b: b.peekable(),
+        }
+    }
+}
+
+impl<'a, K: PrimInt, V> Iterator for Union<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match (self.a.peek(), self.b.peek()) {
+            (Some(x), Some(y)) => match x.cmp(y) {
+                Ordering::Less => self.a.next(),
+                Ordering::Greater => self.b.next(),
+                Ordering::Equal => {
+                    self.b.next();
+                    self.a.next()
+                }
+            },
+            (Some(_), None) => self.a.next(),
+            (None, _) => self.b.next(),
+        }
+    }
+}
+
+pub struct Intersection<'a, K, V>
+where
+    K: PrimInt,
+{
+    a: Peekable<Keys<'a, K, V>>,
+    b: Peekable<Keys<'a, K, V>>,
+}
+
+impl<'a, K: PrimInt, V> Intersection<'a, K, V> {
+    pub(crate) fn new(a: Keys<'a, K, V>, b: Keys<'a, K, V>) -> Intersection<'a, K, V> {
+        Intersection {
+            a: a.peekable(),
+            b: b.peekable(),
+        }
+    }
+}
+
+impl<'a, K: PrimInt, V> Iterator for Intersection<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.a.peek()?.cmp(self.b.peek()?) {
+                Ordering::Less => {
+                    self.a.next();
+                }
+                Ordering::Greater => {
+                    self.b.next();
+                }
+                Ordering::Equal => {
+                    self.b.next();
+                    return self.a.next();
+                }
+            }
+        }
+    }
+}
+
+pub struct Difference<'a, K, V>
+where
+    K: PrimInt,
+{
+    a: Peekable<Keys<'a, K, V>>,
+    b: Peekable<Keys<'a, K, V>>,
+}
+
+impl<'a, K: PrimInt, V> Difference<'a, K, V> {
+    pub(crate) fn new(a: Keys<'a, K, V>, b: Keys<'a, K, V>) -> Difference<'a, K, V> {
+        Difference {
+            a: a.peekable(),
+            b: b.peekable(),
+        }
+    }
+}
+
+impl<'a, K: PrimInt, V> Iterator for Difference<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let x = self.a.peek()?;
+            match self.b.peek().map(|y| x.cmp(y)) {
+                None | Some(Ordering::Less) => return self.a.next(),
+                Some(Ordering::Equal) => {
+                    self.a.next();
+                    self.b.next();
+                }
+                Some(Ordering::Greater) => {
+                    self.b.next();
+                }
+            }
+        }
+    }
+}
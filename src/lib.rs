@@ -1,32 +1,132 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
 extern crate num;
 use num::PrimInt;
 
-use std::ops::Add;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::fmt::{self, Debug};
+use core::hash::{Hash, Hasher};
+use core::ops::{Bound, Index, IndexMut, RangeBounds};
 
-pub struct CritBit<K, V>(Option<CritBitNode<K, V>>)
-where
-    K: PrimInt;
+mod bytes;
+mod cursor;
+mod entry;
+mod iter;
+#[cfg(feature = "serde")]
+mod serde_impl;
+mod set;
 
-enum CritBitNode<K, V>
+pub use bytes::CritBitBytes;
+pub use cursor::CursorMut;
+pub use entry::{Entry, OccupiedEntry, OccupiedError, VacantEntry};
+pub use iter::{
+    Difference, Drain, Intersection, IntoIter, IntoKeys, IntoValues, Iter, IterMut, Keys,
+    PrefixIter, Range, Union, Values, ValuesMut,
+};
+pub use set::CritBitSet;
+
+pub struct CritBit<K, V>
 where
     K: PrimInt,
 {
-    Leaf(K, V),
-    Internal(InternalCritBitNode<K, V>),
+    // Nodes refer to their children by index into this arena, which is kept
+    // dense: a tree of `n` keys is exactly `2n - 1` nodes, with no gaps.
+    nodes: Vec<CritBitNode<K, V>>,
+    // Only meaningful while `nodes` is non-empty.
+    root: u32,
 }
 
-struct InternalCritBitNode<K, V>
+#[derive(Clone)]
+enum CritBitNode<K, V>
 where
     K: PrimInt,
 {
-    left: Option<Box<CritBitNode<K, V>>>,
-    right: Option<Box<CritBitNode<K, V>>>,
+    Leaf(K, V),
+    Internal(InternalCritBitNode),
+}
+
+// Indices are `u32`, so a subtree never holds more leaves than `size` can count.
+#[derive(Clone, Copy)]
+struct InternalCritBitNode {
+    left: u32,
+    right: u32,
     crit: u32,
+    size: u32,
+}
+
+/// Returned by `CritBit::try_insert_bounded` when the new leaf would land
+/// deeper than allowed. `depth` is where it would have gone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DepthExceeded {
+    pub depth: usize,
+    pub max_depth: usize,
+}
+
+impl fmt::Display for DepthExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "inserting would put a leaf at depth {}, past the limit of {}",
+            self.depth, self.max_depth
+        )
+    }
+}
+
+impl core::error::Error for DepthExceeded {}
+
+// Keys as unsigned offsets from `K::min_value()`, so that the distance
+// between any two can be taken without overflowing `K`.
+fn ordinal<K: PrimInt>(key: K) -> u128 {
+    if K::min_value() < K::zero() {
+        key.to_i128().map_or(0, |k| (k as u128) ^ (1 << 127))
+    } else {
+        key.to_u128().unwrap_or(0)
+    }
+}
+
+fn from_ordinal<K: PrimInt>(ordinal: u128) -> K {
+    let key = if K::min_value() < K::zero() {
+        K::from((ordinal ^ (1 << 127)) as i128)
+    } else {
+        K::from(ordinal)
+    };
+    key.expect("Ordinals only come from keys of the same type")
 }
 
+// Where a key falls relative to the tree; see `CritBit::locate`.
+struct Located {
+    subtree: u32,
+    above: Option<bool>,
+    lower: Option<u32>,
+    upper: Option<u32>,
+    before: usize,
+}
+
+// Positions at or past the width of `T` read as zero instead of wrapping
+// around onto a real bit. Both tests are always evaluated: without the short
+// circuit, picking a child compiles to a conditional move rather than a branch
+// that mispredicts on every other level.
+//
+// Signed keys have their sign bit flipped first (`min_value` is just that bit,
+// or zero for unsigned types), so negatives branch left of non-negatives and
+// the tree's order is numeric order. Crit bits come from XORing two keys, where
+// the flip cancels out, so nothing else needs to know.
 #[inline(always)]
 fn bit_at<T: PrimInt>(value: &T, pos: &u32) -> bool {
-    value.rotate_left(*pos).leading_zeros() == 0
+    let biased = *value ^ T::min_value();
+    (*pos < T::zero().count_zeros()) & (biased.rotate_left(*pos).leading_zeros() == 0)
+}
+
+/// How many leading bits `a` and `b` agree on: the key width if they're
+/// equal, otherwise the crit bit a tree holding both branches on.
+pub fn common_prefix_len<K: PrimInt>(a: &K, b: &K) -> u32 {
+    (*a ^ *b).leading_zeros()
 }
 
 impl<K, V> Default for CritBit<K, V>
@@ -42,243 +142,4128 @@ impl<K, V> CritBit<K, V>
 where
     K: PrimInt,
 {
-    pub fn new() -> CritBit<K, V> {
-        CritBit(None)
+    pub const fn new() -> CritBit<K, V> {
+        CritBit {
+            nodes: Vec::new(),
+            root: 0,
+        }
+    }
+
+    /// The width of `K` in bits, which bounds every crit bit and the
+    /// useful range of the `prefix_bits` arguments.
+    pub fn key_bits() -> u32 {
+        K::zero().count_zeros()
+    }
+
+    pub fn singleton(key: K, value: V) -> CritBit<K, V> {
+        CritBit {
+            nodes: alloc::vec![CritBitNode::Leaf(key, value)],
+            root: 0,
+        }
+    }
+
+    /// Creates an empty tree with arena space for about `n` entries, so
+    /// filling it doesn't reallocate along the way. Capacity is only a hint.
+    pub fn with_capacity(n: usize) -> CritBit<K, V> {
+        let mut tree = CritBit::new();
+        tree.reserve(n);
+        tree
     }
 
+    /// Reserves arena space for about `additional` more entries. Like
+    /// `with_capacity`, this is only a hint.
+    pub fn reserve(&mut self, additional: usize) {
+        // Past the first, every key brings a leaf and an internal node.
+        self.nodes.reserve(additional.saturating_mul(2));
+    }
+
+    /// Gives back memory the node arena no longer needs. Removals already
+    /// keep the arena packed, so this only trims its spare capacity.
+    pub fn shrink_to_fit(&mut self) {
+        self.nodes.shrink_to_fit();
+    }
+
+    /// Rebuilds the tree in place. Its shape depends only on its keys, so
+    /// nothing visible changes, but the arena comes back laid out in key order
+    /// with no spare capacity, which is kinder to the cache than the order a
+    /// long run of inserts and removes leaves behind.
+    pub fn rebuild(&mut self) {
+        let all = core::mem::take(self);
+        *self = Self::from_sorted_iter(all);
+        self.shrink_to_fit();
+    }
+
+    /// Builds a tree in linear time from pairs whose keys are strictly
+    /// ascending. The order is only checked in debug builds; unsorted or
+    /// duplicated keys produce a tree that misbehaves on lookup.
+    pub fn from_sorted_slice(pairs: &[(K, V)]) -> CritBit<K, V>
+    where
+        V: Clone,
+    {
+        debug_assert!(
+            pairs.windows(2).all(|w| w[0].0 < w[1].0),
+            "from_sorted_slice requires strictly ascending keys"
+        );
+        Self::from_sorted_iter(pairs.iter().cloned())
+    }
+
+    /// Builds a tree from unordered pairs, settling repeated keys with
+    /// `resolve(key, earlier, later)` in the order they arrive rather than
+    /// letting the last one win. The pairs are sorted first, so the tree
+    /// itself is assembled in linear time.
+    pub fn from_iter_with<I, F>(iter: I, mut resolve: F) -> CritBit<K, V>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        F: FnMut(&K, V, V) -> V,
+    {
+        let mut pairs: Vec<(K, V)> = iter.into_iter().collect();
+        pairs.sort_by_key(|&(k, _)| k);
+        let mut merged: Vec<(K, V)> = Vec::with_capacity(pairs.len());
+        for (k, v) in pairs {
+            if merged.last().is_some_and(|&(last, _)| last == k) {
+                let (_, earlier) = merged.pop().expect("We just checked there was a last pair");
+                merged.push((k, resolve(&k, earlier, v)));
+            } else {
+                merged.push((k, v));
+            }
+        }
+        Self::from_sorted_iter(merged)
+    }
+
+    // The tree is the Cartesian tree of the crit bits between neighbouring
+    // keys, so it can be assembled along a stack of the right spine.
+    fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> CritBit<K, V> {
+        let mut iter = iter.into_iter();
+        let mut tree = CritBit::with_capacity(iter.size_hint().0);
+        let Some((mut last, value)) = iter.next() else {
+            return tree;
+        };
+        let mut spine: Vec<(u32, u32)> = Vec::new();
+        let mut current = tree.push(CritBitNode::Leaf(last, value));
+        for (key, value) in iter {
+            let crit = common_prefix_len(&last, &key);
+            while spine.last().is_some_and(|&(c, _)| c > crit) {
+                let (c, left) = spine
+                    .pop()
+                    .expect("We just checked the spine was non-empty");
+                current = tree.join(left, current, c);
+            }
+            spine.push((crit, current));
+            current = tree.push(CritBitNode::Leaf(key, value));
+            last = key;
+        }
+        while let Some((c, left)) = spine.pop() {
+            current = tree.join(left, current, c);
+        }
+        tree.root = current;
+        tree
+    }
+
+    /// Removes every entry, but keeps the node arena's allocation so that
+    /// refilling the tree doesn't have to go back to the allocator.
     pub fn clear(&mut self) {
-        self.0 = None;
+        self.nodes.clear();
     }
 
     pub fn is_empty(&self) -> bool {
-        self.0.is_none()
+        self.nodes.is_empty()
     }
 
     pub fn len(&self) -> usize {
-        self.0.iter().map(CritBitNode::len).fold(0, Add::add)
+        self.root().map_or(0, |root| self.size(root))
+    }
+
+    /// Internal nodes and leaves together: `2 * len - 1` for a non-empty tree.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: PrimInt,
+    {
+        self.get_key_value(key).map(|(_, v)| v)
+    }
+
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: PrimInt,
+    {
+        let key = Self::owned_key(key)?;
+        match self.leaf(self.best_match(self.root()?, &key)) {
+            (k, v) if *k == key => Some((k, v)),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: PrimInt,
+    {
+        let key = Self::owned_key(key)?;
+        let at = self.best_match(self.root()?, &key);
+        match self.nodes[at as usize] {
+            CritBitNode::Leaf(ref k, ref mut v) if *k == key => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn get_many_mut<const N: usize>(&mut self, keys: [&K; N]) -> Option<[&mut V; N]> {
+        // Pair each key's leaf with where its value goes in the result, then
+        // carve the arena into disjoint borrows in index order.
+        let mut wanted = [(0, 0); N];
+        for (slot, key) in keys.into_iter().enumerate() {
+            let at = self.best_match(self.root()?, key);
+            if *self.leaf(at).0 != *key {
+                return None;
+            }
+            wanted[slot] = (at as usize, slot);
+        }
+        wanted.sort_unstable();
+        if wanted.windows(2).any(|w| w[0].0 == w[1].0) {
+            return None;
+        }
+        let mut found: [Option<&mut V>; N] = core::array::from_fn(|_| None);
+        let (mut rest, mut offset) = (&mut self.nodes[..], 0);
+        for (at, slot) in wanted {
+            let (head, tail) = rest.split_at_mut(at - offset + 1);
+            if let Some(CritBitNode::Leaf(_, v)) = head.last_mut() {
+                found[slot] = Some(v);
+            }
+            (rest, offset) = (tail, at + 1);
+        }
+        Some(found.map(|v| v.expect("Every requested key was found above")))
     }
 
-    pub fn get(&self, key: &K) -> Option<&V> {
-        match &self.0 {
-            Some(node) => node.get(key),
-            &None => None,
+    /// Applies `f` to the value under `key`, returning whether there was one.
+    pub fn update<F: FnOnce(&mut V)>(&mut self, key: &K, f: F) -> bool {
+        match self.get_mut(key) {
+            Some(v) => {
+                f(v);
+                true
+            }
+            None => false,
         }
     }
 
-    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
-        match self.0 {
-            Some(ref mut node) => node.get_mut(key),
-            None => None,
+    /// Swaps the values stored under `a` and `b`, if both are present.
+    pub fn swap(&mut self, a: &K, b: &K) -> bool {
+        if a == b {
+            return self.contains_key(a);
+        }
+        match self.get_many_mut([a, b]) {
+            Some([x, y]) => {
+                core::mem::swap(x, y);
+                true
+            }
+            None => false,
         }
     }
 
-    pub fn contains_key(&self, key: &K) -> bool {
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: PrimInt,
+    {
         self.get(key).is_some()
     }
 
     pub fn insert(&mut self, key: K, value: V) -> Option<V> {
-        match &mut self.0 {
-            &mut Some(ref mut node) => node.insert(key, value),
-            x => {
-                x.replace(CritBitNode::Leaf(key, value));
-                None
+        self.insert_slot(key, value).0
+    }
+
+    /// Inserts `value` under `key`, handing back the entry it replaced.
+    /// Integer keys are only ever equal bit for bit, so the key handed back is
+    /// always the one passed in; it's returned to match maps whose stored keys
+    /// can differ from the lookup key.
+    pub fn replace(&mut self, key: K, value: V) -> Option<(K, V)> {
+        self.insert(key, value).map(|old| (key, old))
+    }
+
+    pub fn insert_checked(&mut self, key: K, value: V) -> (Option<V>, usize) {
+        let (old, _, depth) = self.insert_leaf(key, value);
+        (old, depth)
+    }
+
+    // Like `insert`, but also hands back the slot the value landed in.
+    fn insert_slot(&mut self, key: K, value: V) -> (Option<V>, &mut V) {
+        let (old, at, _) = self.insert_leaf(key, value);
+        (old, self.value_mut(at))
+    }
+
+    // Returns the displaced value along with the index and depth of the leaf
+    // now holding `value`.
+    fn insert_leaf(&mut self, key: K, value: V) -> (Option<V>, u32, usize) {
+        let mut value = Some(value);
+        let (at, depth) = self.find_or_splice(key, || {
+            value
+                .take()
+                .expect("A new leaf is only ever made once per insert")
+        });
+        match value {
+            Some(value) => (
+                Some(core::mem::replace(self.value_mut(at), value)),
+                at,
+                depth,
+            ),
+            None => (None, at, depth),
+        }
+    }
+
+    // The leaf holding `key` and its depth, splicing in a new one built by
+    // `make` if there isn't one yet. A present key is found by the first walk
+    // alone; only a missing one needs the second walk down to where it goes.
+    fn find_or_splice<F: FnOnce() -> V>(&mut self, key: K, make: F) -> (u32, usize) {
+        let Some(root) = self.root() else {
+            self.root = self.push(CritBitNode::Leaf(key, make()));
+            return (self.root, 0);
+        };
+        let (mut found, mut depth) = (root, 0);
+        while let CritBitNode::Internal(InternalCritBitNode {
+            left, right, crit, ..
+        }) = self.nodes[found as usize]
+        {
+            found = if bit_at(&key, &crit) { right } else { left };
+            depth += 1;
+        }
+        let crit = common_prefix_len(self.leaf(found).0, &key);
+        if crit == Self::key_bits() {
+            return (found, depth);
+        }
+
+        // Walk `key`'s path down to the first node branching at or after
+        // `crit`, counting the new leaf into each subtree passed on the way.
+        let (mut parent, mut at, mut depth) = (None, root, 0);
+        while let CritBitNode::Internal(InternalCritBitNode {
+            left,
+            right,
+            crit: c,
+            ref mut size,
+        }) = self.nodes[at as usize]
+        {
+            if c >= crit {
+                break;
             }
+            *size += 1;
+            let side = bit_at(&key, &c);
+            parent = Some((at, side));
+            at = if side { right } else { left };
+            depth += 1;
         }
+        let leaf = self.push(CritBitNode::Leaf(key, make()));
+        let joint = if bit_at(&key, &crit) {
+            self.join(at, leaf, crit)
+        } else {
+            self.join(leaf, at, crit)
+        };
+        self.relink(parent, joint);
+        (leaf, depth + 1)
     }
-}
 
-impl<K: PrimInt, V> CritBitNode<K, V> {
-    fn len(&self) -> usize {
-        match *self {
-            CritBitNode::Leaf(..) => 1,
-            CritBitNode::Internal(InternalCritBitNode {
-                ref left,
-                ref right,
-                ..
-            }) => left
-                .iter()
-                .chain(right.iter())
-                .map(|x| x.len())
-                .fold(0, Add::add),
+    /// Inserts like `insert`, unless `key` is new and its leaf would sit
+    /// more than `max_depth` internal nodes below the root, in which case the
+    /// tree is left untouched. Replacing a present key's value always works.
+    /// Only the new leaf is bounded: leaves already below the point where it
+    /// branches off each move one level deeper too.
+    pub fn try_insert_bounded(
+        &mut self,
+        key: K,
+        value: V,
+        max_depth: usize,
+    ) -> Result<Option<V>, DepthExceeded> {
+        if let Some(depth) = self.splice_depth(&key)
+            && depth > max_depth
+        {
+            return Err(DepthExceeded { depth, max_depth });
         }
+        Ok(self.insert(key, value))
     }
 
-    fn get(&self, key: &K) -> Option<&V> {
-        match *self {
-            CritBitNode::Leaf(ref k, ref v) if *k == *key => Some(v),
-            CritBitNode::Internal(InternalCritBitNode {
-                left: Some(ref left),
-                right: _,
-                ref crit,
-            }) if !bit_at(key, crit) => left.get(key),
-            CritBitNode::Internal(InternalCritBitNode {
-                left: _,
-                right: Some(ref right),
-                ref crit,
-            }) if bit_at(key, crit) => right.get(key),
-            _ => None,
+    // How deep a new leaf for `key` would land, or `None` if it's present.
+    fn splice_depth(&self, key: &K) -> Option<usize> {
+        let Some(root) = self.root() else {
+            return Some(0);
+        };
+        let crit = common_prefix_len(self.leaf(self.best_match(root, key)).0, key);
+        if crit == Self::key_bits() {
+            return None;
+        }
+        let (mut at, mut depth) = (root, 0);
+        while let CritBitNode::Internal(InternalCritBitNode {
+            left,
+            right,
+            crit: c,
+            ..
+        }) = self.nodes[at as usize]
+        {
+            if c >= crit {
+                break;
+            }
+            at = if bit_at(key, &c) { right } else { left };
+            depth += 1;
         }
+        Some(depth + 1)
     }
 
-    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
-        match *self {
-            CritBitNode::Leaf(ref k, ref mut v) if *k == *key => Some(v),
-            CritBitNode::Internal(InternalCritBitNode {
-                left: Some(ref mut kid),
-                right: _,
-                ref crit,
-            }) if !bit_at(key, crit) => kid.get_mut(key),
-            CritBitNode::Internal(InternalCritBitNode {
-                left: _,
-                right: Some(ref mut kid),
-                ref crit,
-            }) if bit_at(key, crit) => kid.get_mut(key),
-            _ => None,
+    pub fn extend_from_slice(&mut self, pairs: &[(K, V)])
+    where
+        V: Copy,
+    {
+        self.extend(pairs.iter().copied());
+    }
+
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        if self.contains_key(&key) {
+            Entry::Occupied(OccupiedEntry { tree: self, key })
+        } else {
+            Entry::Vacant(VacantEntry { tree: self, key })
         }
     }
 
-    fn insert(&mut self, key: K, value: V) -> Option<V> {
-        match *self {
-            CritBitNode::Leaf(ref k, ref mut v) if *k == key => Some(std::mem::replace(v, value)),
-            CritBitNode::Leaf(..) => {
-                if let CritBitNode::Leaf(k, v) = std::mem::replace(
-                    self,
-                    CritBitNode::Internal(InternalCritBitNode {
-                        left: None,
-                        right: None,
-                        crit: 0,
-                    }),
-                ) {
-                    let crit = (k ^ key).leading_zeros();
-                    let _ = std::mem::replace(
-                        self,
-                        CritBitNode::Internal({
-                            let (left, right) = if k < key {
-                                (
-                                    Some(Box::new(CritBitNode::Leaf(k, v))),
-                                    Some(Box::new(CritBitNode::Leaf(key, value))),
-                                )
-                            } else {
-                                (
-                                    Some(Box::new(CritBitNode::Leaf(key, value))),
-                                    Some(Box::new(CritBitNode::Leaf(k, v))),
-                                )
-                            };
-                            InternalCritBitNode { left, right, crit }
-                        }),
-                    );
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<&mut V, OccupiedError<'_, K, V>> {
+        match self.entry(key) {
+            Entry::Occupied(entry) => Err(OccupiedError { entry, value }),
+            Entry::Vacant(entry) => Ok(entry.insert(value)),
+        }
+    }
+
+    /// The value under `key`, inserting `default` first if there isn't one.
+    /// Unlike going through `entry`, a present key costs a single descent.
+    pub fn get_or_insert(&mut self, key: K, default: V) -> &mut V {
+        self.get_or_insert_with(key, || default)
+    }
+
+    /// Inserts only if `key` is missing, reporting whether it did. A value
+    /// that's already there is left alone and `value` is dropped.
+    pub fn insert_if_absent(&mut self, key: K, value: V) -> bool {
+        let mut inserted = false;
+        self.find_or_splice(key, || {
+            inserted = true;
+            value
+        });
+        inserted
+    }
+
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        let (at, _) = self.find_or_splice(key, f);
+        self.value_mut(at)
+    }
+
+    pub fn entry_or_default(&mut self, key: K) -> &mut V
+    where
+        V: Default,
+    {
+        self.get_or_insert_with(key, V::default)
+    }
+
+    pub fn contains_value(&self, value: &V) -> bool
+    where
+        V: PartialEq,
+    {
+        self.values().any(|v| *v == *value)
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: PrimInt,
+    {
+        let key = &Self::owned_key(key)?;
+        let leaf = self.best_match(self.root()?, key);
+        if *self.leaf(leaf).0 != *key {
+            return None;
+        }
+
+        // Walk back down, counting the key out of every subtree above the
+        // leaf's parent, then promote its sibling into the parent's place.
+        let (mut grandparent, mut parent, mut at) = (None, None, self.root);
+        while let CritBitNode::Internal(InternalCritBitNode {
+            left,
+            right,
+            crit,
+            ref mut size,
+        }) = self.nodes[at as usize]
+        {
+            let side = bit_at(key, &crit);
+            let next = if side { right } else { left };
+            if next == leaf {
+                parent = Some((at, side));
+                break;
+            }
+            *size -= 1;
+            grandparent = Some((at, side));
+            at = next;
+        }
+        let Some((parent, side)) = parent else {
+            return match self.nodes.pop() {
+                Some(CritBitNode::Leaf(_, v)) => Some(v),
+                _ => unreachable!("We just checked that this was a leaf..."),
+            };
+        };
+        let sibling = self.child(parent, !side);
+        self.relink(grandparent, sibling);
+
+        // Freeing the higher slot first means the lower one can't be the node
+        // that gets moved into it.
+        let high = self.release(leaf.max(parent));
+        let low = self.release(leaf.min(parent));
+        match (high, low) {
+            (CritBitNode::Leaf(_, v), _) | (_, CritBitNode::Leaf(_, v)) => Some(v),
+            _ => unreachable!("We just checked that this was a leaf..."),
+        }
+    }
+
+    /// Removes each of `keys` that's present, returning how many were. The
+    /// keys are sorted first so that neighbouring removals walk the same
+    /// paths, and a key listed twice only counts once.
+    pub fn bulk_remove<I: IntoIterator<Item = K>>(&mut self, keys: I) -> usize {
+        let mut keys: Vec<K> = keys.into_iter().collect();
+        keys.sort_unstable();
+        keys.dedup();
+        keys.iter().filter(|k| self.remove(*k).is_some()).count()
+    }
+
+    // Removing a key is a couple of walks down the tree, while rebuilding is
+    // linear in all of it, so moving `count` entries one at a time only pays
+    // while they're a small share of the tree.
+    fn few(&self, count: usize) -> bool {
+        count.saturating_mul(32) < self.len()
+    }
+
+    // Takes out `doomed`, which must all be present and ascending.
+    fn remove_sorted(&mut self, doomed: Vec<K>) {
+        if self.few(doomed.len()) {
+            for k in &doomed {
+                self.remove(k);
+            }
+            return;
+        }
+        let mut doomed = doomed.into_iter().peekable();
+        let all = core::mem::take(self);
+        *self = Self::from_sorted_iter(
+            all.into_iter()
+                .filter(|(k, _)| doomed.next_if_eq(k).is_none()),
+        );
+    }
+
+    /// Keeps only the entries `f` accepts. Nothing moves when it accepts them
+    /// all, and when it turns down only a few they're removed one by one;
+    /// otherwise the survivors are rebuilt into a fresh arena.
+    pub fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+        let doomed = self
+            .iter_mut()
+            .filter_map(|(k, v)| (!f(k, v)).then_some(*k))
+            .collect();
+        self.remove_sorted(doomed);
+    }
+
+    pub fn retain_keys<F: FnMut(&K) -> bool>(&mut self, mut f: F) {
+        self.retain(|k, _| f(k));
+    }
+
+    /// Collapses each run of consecutive entries whose values `same` considers
+    /// equal down to the run's first, smallest-keyed entry. Every later value
+    /// is compared against the one that's kept, not against its neighbour.
+    pub fn coalesce<F: FnMut(&V, &V) -> bool>(&mut self, mut same: F) {
+        let mut kept: Option<&V> = None;
+        let keep: Vec<bool> = self
+            .values()
+            .map(|v| {
+                let redundant = kept.is_some_and(|k| same(k, v));
+                if !redundant {
+                    kept = Some(v);
+                }
+                !redundant
+            })
+            .collect();
+        if keep.iter().all(|&k| k) {
+            return;
+        }
+        let all = core::mem::take(self);
+        *self = Self::from_sorted_iter(
+            all.into_iter()
+                .zip(keep)
+                .filter_map(|(entry, keep)| keep.then_some(entry)),
+        );
+    }
+
+    /// Turns every value into a new one with `f`, keeping the keys. A tree's
+    /// shape is fixed by its keys alone, so rebuilding from the ordered
+    /// entries lays out the same skeleton over the new values.
+    pub fn map_values<W, F: FnMut(&K, V) -> W>(self, mut f: F) -> CritBit<K, W> {
+        CritBit::from_sorted_iter(self.into_iter().map(|(k, v)| {
+            let w = f(&k, v);
+            (k, w)
+        }))
+    }
+
+    pub fn split_off(&mut self, key: &K) -> CritBit<K, V> {
+        let low = self.split_at_rank(self.rank(key));
+        core::mem::replace(self, low)
+    }
+
+    /// Moves the `n` smallest entries into the returned tree. The cut follows
+    /// the path the cached sizes pick out for rank `n`, taking each subtree
+    /// on the far side of it off in one step, so reshaping the two trees is
+    /// O(height). The smaller side's entries still have to move into an
+    /// arena of their own, while the larger side keeps the one it has.
+    pub fn split_at_rank(&mut self, n: usize) -> CritBit<K, V> {
+        let n = n.min(self.len());
+        if n <= self.len() / 2 {
+            Self::from_taken(self.take_ranks(0, n))
+        } else {
+            let high = Self::from_taken(self.take_ranks(n, self.len()));
+            core::mem::replace(self, high)
+        }
+    }
+
+    /// Splits the entries by `bit`, counted from the most significant end as
+    /// crit bits are: those reading 0 there go in the first tree, and those
+    /// reading 1 in the second. The bit is the key's own, as `prefix_iter`
+    /// reads it, so a signed key's sign bit is 1 when it's negative. The
+    /// root's crit bit shows at a glance when every key agrees on `bit`, and
+    /// then the whole tree moves over as is. When `bit` is the root's crit
+    /// bit, the root's two children are detached as the two trees, the
+    /// smaller moving into an arena of its own. Only a `bit` further down
+    /// has every key looked at.
+    pub fn split_by_bit(mut self, bit: u32) -> (CritBit<K, V>, CritBit<K, V>) {
+        let Some(root) = self.root() else {
+            return (self, CritBit::new());
+        };
+        let crit = match self.nodes[root as usize] {
+            CritBitNode::Internal(InternalCritBitNode { crit, .. }) => Some(crit),
+            CritBitNode::Leaf(..) => None,
+        };
+        // `bit_at` undoes the sign flip of a key flipped beforehand.
+        let raw_bit = |k: &K| bit_at(&(*k ^ K::min_value()), &bit);
+        match crit.map(|crit| crit.cmp(&bit)) {
+            None | Some(Ordering::Greater) => {
+                if raw_bit(self.leaf(self.edge(root, false)).0) {
+                    (CritBit::new(), self)
                 } else {
-                    unreachable!("We just checked that this was a leaf...")
+                    (self, CritBit::new())
                 }
-                None
             }
-            CritBitNode::Internal(InternalCritBitNode {
-                left: Some(ref mut kid),
-                right: _,
-                ref crit,
-            }) if !bit_at(&key, crit) => kid.insert(key, value),
-            CritBitNode::Internal(InternalCritBitNode {
-                left: _,
-                right: Some(ref mut kid),
-                ref crit,
-            }) if bit_at(&key, crit) => kid.insert(key, value),
-            _ => unreachable!(
-                "Internal nodes should always have both branches filled, what happened?"
-            ),
+            Some(Ordering::Equal) => {
+                // A signed key's sign bit reads the other way round from
+                // the tree's, so ask the left child which side it's on.
+                let left = self.child(root, false);
+                let left_is_ones = raw_bit(self.leaf(self.edge(left, false)).0);
+                let low = self.split_at_rank(self.size(left));
+                if left_is_ones {
+                    (self, low)
+                } else {
+                    (low, self)
+                }
+            }
+            Some(Ordering::Less) => {
+                let (ones, zeros): (Vec<_>, Vec<_>) =
+                    self.into_iter().partition(|(k, _)| raw_bit(k));
+                (Self::from_sorted_iter(zeros), Self::from_sorted_iter(ones))
+            }
         }
     }
-}
 
-#[cfg(test)]
-mod test {
-    use crate::{CritBit, bit_at};
+    pub fn append(&mut self, other: &mut CritBit<K, V>) {
+        if self.is_empty() {
+            core::mem::swap(self, other);
+            return;
+        }
+        self.extend(core::mem::take(other));
+    }
 
-    #[test]
-    fn verify_bit_at() {
-        assert!(!bit_at(&1u8, &0u32));
-        assert!(bit_at(&128u8, &0u32));
-        assert!(bit_at(&1u8, &7u32));
-        assert!(!bit_at(&128u8, &7u32));
+    /// Removes every entry with a key in `range`, returning how many went.
+    /// The span is found from the cached sizes, and only the two paths to its
+    /// ends are walked: every subtree lying wholly inside it is cut off in one
+    /// step, and the sizes are fixed up on the way back up. The removed nodes
+    /// still have to be dropped and the arena closed up behind them.
+    pub fn remove_range<R: RangeBounds<K>>(&mut self, range: R) -> usize {
+        let (start, end) = self.range_ranks(&range);
+        if start >= end {
+            return 0;
+        }
+        self.take_ranks(start, end);
+        end - start
     }
 
-    #[test]
-    fn empty_len() {
-        let t: CritBit<u8, ()> = CritBit::new();
-        assert_eq!(t.len(), 0);
+    /// Like `retain`, but `f` only gets a say over entries in `range`; those
+    /// outside it are kept without being shown to `f`, nor walked over.
+    pub fn retain_range<R, F>(&mut self, range: R, mut f: F)
+    where
+        R: RangeBounds<K>,
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        let walk = match range.start_bound() {
+            Bound::Included(k) | Bound::Excluded(k) => IterMut::seek(self, k),
+            Bound::Unbounded => self.iter_mut(),
+        };
+        // The walk starts at the first key not below the start, so all that's
+        // left to check is the end, bar an excluded start that's present.
+        let doomed = walk
+            .skip_while(|(k, _)| range.start_bound() == Bound::Excluded(*k))
+            .take_while(|(k, _)| range.contains(*k))
+            .filter_map(|(k, v)| (!f(k, v)).then_some(*k))
+            .collect();
+        self.remove_sorted(doomed);
     }
 
-    #[test]
-    fn empty_contains_key() {
-        let t: CritBit<u8, ()> = CritBit::new();
-        assert!(!t.contains_key(&0u8));
-        assert!(!t.contains_key(&128u8));
-        assert!(!t.contains_key(&255u8));
+    /// Like `append`, but a key already in `self` keeps its slot and `f` folds
+    /// the incoming value into it.
+    pub fn merge_with<F: FnMut(&K, &mut V, V)>(&mut self, other: CritBit<K, V>, mut f: F) {
+        if self.is_empty() {
+            *self = other;
+            return;
+        }
+        for (k, v) in other {
+            match self.entry(k) {
+                Entry::Occupied(mut e) => f(&k, e.get_mut(), v),
+                Entry::Vacant(e) => {
+                    e.insert(v);
+                }
+            }
+        }
     }
 
-    #[test]
-    fn empty_get() {
-        let t: CritBit<u8, ()> = CritBit::new();
-        assert_eq!(t.get(&0u8), None);
-        assert_eq!(t.get(&128u8), None);
-        assert_eq!(t.get(&255u8), None);
+    pub fn pop_min(&mut self) -> Option<(K, V)> {
+        self.pop_edge(false)
     }
 
-    #[test]
-    fn empty_get_mut() {
-        let mut t: CritBit<u8, ()> = CritBit::new();
-        assert!(t.get_mut(&0u8).is_none());
-        assert!(t.get_mut(&128u8).is_none());
-        assert!(t.get_mut(&255u8).is_none());
+    pub fn pop_max(&mut self) -> Option<(K, V)> {
+        self.pop_edge(true)
     }
 
-    #[test]
-    fn insert_len() {
-        let mut t: CritBit<u8, ()> = CritBit::new();
-        assert_eq!(t.len(), 0);
+    fn pop_edge(&mut self, right: bool) -> Option<(K, V)> {
+        let key = *self.leaf(self.edge(self.root()?, right)).0;
+        self.remove(&key).map(|v| (key, v))
+    }
 
-        t.insert(0u8, ());
-        assert_eq!(t.len(), 1)
+    pub fn first_entry(&mut self) -> Option<OccupiedEntry<'_, K, V>> {
+        let key = *self.first()?.0;
+        Some(OccupiedEntry { tree: self, key })
     }
 
-    #[test]
-    fn insert_contains_key() {
-        let mut t: CritBit<u8, ()> = CritBit::new();
-        assert!(!t.contains_key(&0u8));
+    pub fn last_entry(&mut self) -> Option<OccupiedEntry<'_, K, V>> {
+        let key = *self.last()?.0;
+        Some(OccupiedEntry { tree: self, key })
+    }
 
-        t.insert(0u8, ());
-        assert!(t.contains_key(&0u8));
+    pub fn min_key(&self) -> Option<&K> {
+        self.first().map(|(k, _)| k)
     }
 
-    #[test]
-    fn insert_get() {
-        let mut t: CritBit<u8, u8> = CritBit::new();
-        assert_eq!(t.get(&0u8), None);
+    pub fn max_key(&self) -> Option<&K> {
+        self.last().map(|(k, _)| k)
+    }
 
-        t.insert(0u8, 1u8);
-        assert_eq!(t.get(&0u8), Some(&1u8));
+    pub fn first(&self) -> Option<(&K, &V)> {
+        Some(self.leaf(self.edge(self.root()?, false)))
     }
 
-    #[test]
-    fn insert_get_mut() {
-        let mut t: CritBit<u8, u8> = CritBit::new();
-        assert_eq!(t.get(&0u8), None);
+    pub fn last(&self) -> Option<(&K, &V)> {
+        Some(self.leaf(self.edge(self.root()?, true)))
+    }
 
-        t.insert(0u8, 1u8);
-        assert_eq!(t.get(&0u8), Some(&1u8));
+    /// A cursor on the first entry whose key is `>= key`, or off the end of
+    /// the tree if there isn't one.
+    pub fn lower_bound_mut(&mut self, key: &K) -> CursorMut<'_, K, V> {
+        CursorMut::lower_bound(self, key)
+    }
 
-        *t.get_mut(&0u8).unwrap() = 2u8;
-        assert_eq!(t.get(&0u8), Some(&2u8));
+    pub fn peek_min_mut(&mut self) -> Option<(&K, &mut V)> {
+        let at = self.edge(self.root()?, false);
+        Some(self.leaf_mut(at))
     }
 
-    #[test]
-    fn insert_insert() {
-        let mut t: CritBit<u8, u8> = CritBit::new();
-        assert_eq!(t.get(&0u8), None);
+    pub fn peek_max_mut(&mut self) -> Option<(&K, &mut V)> {
+        let at = self.edge(self.root()?, true);
+        Some(self.leaf_mut(at))
+    }
 
-        t.insert(0u8, 1u8);
-        assert_eq!(t.get(&0u8), Some(&1u8));
+    pub fn rank(&self, key: &K) -> usize {
+        let Some(root) = self.root() else {
+            return 0;
+        };
+        let located = self.locate(root, key);
+        match located.above {
+            Some(true) => located.before + self.size(located.subtree),
+            None | Some(false) => located.before,
+        }
+    }
 
-        assert_eq!(t.insert(0u8, 2u8), Some(1u8));
-        assert_eq!(t.get(&0u8), Some(&2u8));
+    /// The index of `key` among the keys in ascending order, or `None` if
+    /// it isn't there.
+    pub fn position_of(&self, key: &K) -> Option<usize> {
+        let located = self.locate(self.root()?, key);
+        located.above.is_none().then_some(located.before)
+    }
+
+    pub fn count_range<R: RangeBounds<K>>(&self, range: R) -> usize {
+        let (start, end) = self.range_ranks(&range);
+        end.saturating_sub(start)
+    }
+
+    // The ranks of the first entry in `range` and of the first entry past it.
+    fn range_ranks<R: RangeBounds<K>>(&self, range: &R) -> (usize, usize) {
+        // The number of keys strictly below the bound, or up to and including
+        // it when it's inclusive from this side.
+        let below = |bound: Bound<&K>, inclusive: bool| match bound {
+            Bound::Included(k) if inclusive => self.rank(k) + usize::from(self.contains_key(k)),
+            Bound::Excluded(k) if !inclusive => self.rank(k) + usize::from(self.contains_key(k)),
+            Bound::Included(k) | Bound::Excluded(k) => self.rank(k),
+            Bound::Unbounded if inclusive => self.len(),
+            Bound::Unbounded => 0,
+        };
+        (
+            below(range.start_bound(), false),
+            below(range.end_bound(), true),
+        )
+    }
+
+    /// Adds up the values of the entries with keys in `range`. The walk
+    /// starts at the range's lower end, so nothing before it is visited.
+    pub fn sum_values_in_range<R, S>(&self, range: R) -> S
+    where
+        R: RangeBounds<K>,
+        V: Clone + Into<S>,
+        S: core::iter::Sum,
+    {
+        self.range(range).map(|(_, v)| v.clone().into()).sum()
+    }
+
+    pub fn select(&self, mut n: usize) -> Option<(&K, &V)> {
+        if n >= self.len() {
+            return None;
+        }
+        let mut at = self.root;
+        while let CritBitNode::Internal(InternalCritBitNode { left, right, .. }) =
+            self.nodes[at as usize]
+        {
+            if n < self.size(left) {
+                at = left;
+            } else {
+                n -= self.size(left);
+                at = right;
+            }
+        }
+        Some(self.leaf(at))
+    }
+
+    /// The first entry whose key fails `pred`, which must hold for some
+    /// prefix of the keys in ascending order and fail for the rest. Each
+    /// branch is settled by testing the largest key on its left side.
+    pub fn partition_point<P: FnMut(&K) -> bool>(&self, mut pred: P) -> Option<(&K, &V)> {
+        let mut at = self.root()?;
+        while let CritBitNode::Internal(InternalCritBitNode { left, right, .. }) =
+            self.nodes[at as usize]
+        {
+            at = if pred(self.leaf(self.edge(left, true)).0) {
+                right
+            } else {
+                left
+            };
+        }
+        let (k, v) = self.leaf(at);
+        if pred(k) { None } else { Some((k, v)) }
+    }
+
+    /// The smallest key in `start..end` that isn't in the tree, for handing
+    /// out free IDs. Runs of consecutive keys are skipped with a binary search
+    /// over ranks rather than walked one at a time.
+    pub fn first_missing_in(&self, start: K, end: K) -> Option<K> {
+        if start >= end {
+            return None;
+        }
+        // The entry `n` places past `start`'s rank is `start + n` exactly when
+        // nothing between them is missing.
+        let base = self.rank(&start);
+        let no_gap = |at: usize| {
+            self.select(at)
+                .is_some_and(|(k, _)| ordinal(*k) - ordinal(start) == (at - base) as u128)
+        };
+        let (mut lo, mut hi) = (base, self.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if no_gap(mid) {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        let missing = ordinal(start) + (lo - base) as u128;
+        (missing < ordinal(end)).then(|| from_ordinal(missing))
+    }
+
+    pub fn floor(&self, key: &K) -> Option<(&K, &V)> {
+        let located = self.locate(self.root()?, key);
+        let at = match located.above {
+            None | Some(true) => located.subtree,
+            Some(false) => located.lower?,
+        };
+        Some(self.leaf(self.edge(at, true)))
+    }
+
+    pub fn ceiling(&self, key: &K) -> Option<(&K, &V)> {
+        let located = self.locate(self.root()?, key);
+        let at = match located.above {
+            None | Some(false) => located.subtree,
+            Some(true) => located.upper?,
+        };
+        Some(self.leaf(self.edge(at, false)))
+    }
+
+    pub fn nearest(&self, key: &K) -> Option<(&K, &V)> {
+        match (self.floor(key), self.ceiling(key)) {
+            (Some(lo), Some(hi)) => {
+                // A distance that overflows `K` is further than any that doesn't.
+                match (key.checked_sub(lo.0), hi.0.checked_sub(key)) {
+                    (Some(down), Some(up)) if up < down => Some(hi),
+                    (None, Some(_)) => Some(hi),
+                    _ => Some(lo),
+                }
+            }
+            (lo, hi) => lo.or(hi),
+        }
+    }
+
+    /// The stored key sharing the longest run of leading bits with `key`, which
+    /// is exactly where a lookup for `key` ends up. Ties go to whichever of the
+    /// equally long matches that walk reaches.
+    pub fn longest_matching_prefix(&self, key: &K) -> Option<(&K, &V)> {
+        Some(self.leaf(self.best_match(self.root()?, key)))
+    }
+
+    pub fn longest_prefix_match(&self, key: &K, prefix_bits: u32) -> Option<(&K, &V)> {
+        let (k, v) = self.longest_matching_prefix(key)?;
+        if common_prefix_len(k, key) >= prefix_bits.min(Self::key_bits()) {
+            Some((k, v))
+        } else {
+            None
+        }
+    }
+
+    pub fn contains_prefix(&self, prefix: K, prefix_bits: u32) -> bool {
+        self.root()
+            .and_then(|root| self.prefix_subtree(root, &prefix, prefix_bits))
+            .is_some()
+    }
+
+    pub fn prefix_iter(&self, prefix: K, prefix_bits: u32) -> PrefixIter<'_, K, V> {
+        PrefixIter(Iter::new(
+            &self.nodes,
+            self.root()
+                .and_then(|root| self.prefix_subtree(root, &prefix, prefix_bits)),
+        ))
+    }
+
+    /// Folds each group of keys sharing their top `prefix_bits` bits into one
+    /// accumulator, starting from `A::default()`. Groups come back in
+    /// ascending order, each under its prefix with the lower bits cleared.
+    pub fn group_by_prefix<A, F>(&self, prefix_bits: u32, mut fold: F) -> Vec<(K, A)>
+    where
+        A: Default,
+        F: FnMut(A, &K, &V) -> A,
+    {
+        let prefix_bits = prefix_bits.min(Self::key_bits());
+        let mask = match prefix_bits {
+            0 => K::zero(),
+            bits => !K::zero() << (Self::key_bits() - bits) as usize,
+        };
+        let mut groups = Vec::new();
+        let mut stack: Vec<u32> = self.root().into_iter().collect();
+        while let Some(at) = stack.pop() {
+            // Only a branch inside the prefix splits a group; below one that
+            // doesn't, the whole subtree shares a prefix.
+            if let CritBitNode::Internal(InternalCritBitNode {
+                left, right, crit, ..
+            }) = self.nodes[at as usize]
+                && crit < prefix_bits
+            {
+                stack.push(right);
+                stack.push(left);
+                continue;
+            }
+            let acc =
+                Iter::new(&self.nodes, Some(at)).fold(A::default(), |acc, (k, v)| fold(acc, k, v));
+            groups.push((*self.leaf(self.edge(at, false)).0 & mask, acc));
+        }
+        groups
+    }
+
+    /// The bit the root branches on, counted from the most significant end.
+    pub fn root_crit_bit(&self) -> Option<u32> {
+        match self.nodes[self.root()? as usize] {
+            CritBitNode::Internal(InternalCritBitNode { crit, .. }) => Some(crit),
+            CritBitNode::Leaf(..) => None,
+        }
+    }
+
+    /// Renders the tree in Graphviz DOT: internal nodes are labelled with
+    /// their crit bit, leaves with their key, and edges with the bit value
+    /// that leads down them.
+    pub fn to_dot(&self) -> String
+    where
+        K: Debug,
+    {
+        use core::fmt::Write;
+
+        let mut dot = String::from("digraph critbit {\n");
+        let mut stack: Vec<u32> = self.root().into_iter().collect();
+        while let Some(at) = stack.pop() {
+            // Writing into a `String` can't fail.
+            let _ = match self.nodes[at as usize] {
+                CritBitNode::Leaf(ref k, _) => {
+                    writeln!(dot, "    n{at} [shape=box, label=\"{k:?}\"];")
+                }
+                CritBitNode::Internal(InternalCritBitNode {
+                    left, right, crit, ..
+                }) => {
+                    stack.push(right);
+                    stack.push(left);
+                    writeln!(dot, "    n{at} [label=\"crit {crit}\"];")
+                        .and_then(|()| writeln!(dot, "    n{at} -> n{left} [label=\"0\"];"))
+                        .and_then(|()| writeln!(dot, "    n{at} -> n{right} [label=\"1\"];"))
+                }
+            };
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.depths().0
+    }
+
+    pub fn avg_depth(&self) -> f64 {
+        match self.len() {
+            0 => 0.0,
+            n => self.depths().1 as f64 / n as f64,
+        }
+    }
+
+    // The longest root-to-leaf path and the total over all of them, counted
+    // in internal nodes passed through.
+    fn depths(&self) -> (usize, usize) {
+        let (mut max, mut total) = (0, 0);
+        let mut stack: Vec<(u32, usize)> = self.root().map(|root| (root, 0)).into_iter().collect();
+        while let Some((at, depth)) = stack.pop() {
+            match self.nodes[at as usize] {
+                CritBitNode::Leaf(..) => {
+                    max = max.max(depth);
+                    total += depth;
+                }
+                CritBitNode::Internal(InternalCritBitNode { left, right, .. }) => {
+                    stack.push((left, depth + 1));
+                    stack.push((right, depth + 1));
+                }
+            }
+        }
+        (max, total)
+    }
+
+    /// Walks the whole tree checking its structure: every index points into
+    /// the arena, crit bits strictly increase downwards, each node's crit bit
+    /// is exactly where its two sides first disagree, cached sizes match, and
+    /// every node in the arena is reached. Meant for debugging and fuzzing;
+    /// it's linear in the tree size.
+    pub fn check_invariants(&self) -> Result<(), String> {
+        let Some(root) = self.root() else {
+            return Ok(());
+        };
+        let (_, len) = self.check(root, None)?;
+        if self.nodes.len() != 2 * len - 1 {
+            return Err(format!(
+                "the arena holds {} nodes but the tree only reaches {}",
+                self.nodes.len(),
+                2 * len - 1
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether `other` holds exactly the same entries, for using a
+    /// `BTreeMap` as the reference model in randomized tests.
+    pub fn eq_btreemap(&self, other: &BTreeMap<K, V>) -> bool
+    where
+        V: PartialEq,
+    {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+
+    pub fn into_sorted_vec(self) -> Vec<(K, V)> {
+        let mut sorted = Vec::with_capacity(self.len());
+        sorted.extend(self);
+        sorted
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter::new(&self.nodes, self.root())
+    }
+
+    /// Iterates in ascending order from the first key `>= key`. The smaller
+    /// keys are never visited, so this costs a single descent to start.
+    pub fn iter_from(&self, key: &K) -> Iter<'_, K, V> {
+        Iter::seek(self, key)
+    }
+
+    /// The `N` smallest entries with keys `>= start`, padded out with `None`
+    /// if there aren't that many. Each step is a fresh descent from the root,
+    /// so unlike iterating this never allocates.
+    pub fn get_window<const N: usize>(&self, start: &K) -> [Option<(&K, &V)>; N] {
+        let mut next = self.ceiling(start);
+        core::array::from_fn(|_| {
+            let here = next?;
+            next = self
+                .neighbour(self.root, here.0, true)
+                .map(|at| self.leaf(at));
+            Some(here)
+        })
+    }
+
+    /// Calls `f` on every entry in ascending key order.
+    pub fn for_each<F: FnMut(&K, &V)>(&self, mut f: F) {
+        let _ = self.try_for_each(|k, v| {
+            f(k, v);
+            Ok::<(), core::convert::Infallible>(())
+        });
+    }
+
+    /// Like `for_each`, but stops at the first error `f` returns and hands
+    /// it back.
+    pub fn try_for_each<E, F: FnMut(&K, &V) -> Result<(), E>>(&self, mut f: F) -> Result<(), E> {
+        let mut stack: Vec<u32> = self.root().into_iter().collect();
+        while let Some(at) = stack.pop() {
+            match self.nodes[at as usize] {
+                CritBitNode::Leaf(ref k, ref v) => f(k, v)?,
+                CritBitNode::Internal(InternalCritBitNode { left, right, .. }) => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut::new(self)
+    }
+
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V> {
+        Range::new(
+            self,
+            range.start_bound().cloned(),
+            range.end_bound().cloned(),
+        )
+    }
+
+    pub fn drain(&mut self) -> Drain<'_, K, V> {
+        Drain::new(self)
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys(self.iter())
+    }
+
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values(self.iter())
+    }
+
+    pub fn union_keys<'a>(&'a self, other: &'a CritBit<K, V>) -> Union<'a, K, V> {
+        Union::new(self.keys(), other.keys())
+    }
+
+    pub fn intersection_keys<'a>(&'a self, other: &'a CritBit<K, V>) -> Intersection<'a, K, V> {
+        Intersection::new(self.keys(), other.keys())
+    }
+
+    pub fn difference_keys<'a>(&'a self, other: &'a CritBit<K, V>) -> Difference<'a, K, V> {
+        Difference::new(self.keys(), other.keys())
+    }
+
+    pub fn into_keys(self) -> IntoKeys<K, V> {
+        IntoKeys(self.into_iter())
+    }
+
+    pub fn into_values(self) -> IntoValues<K, V> {
+        IntoValues(self.into_iter())
+    }
+
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        ValuesMut(self.iter_mut())
+    }
+}
+
+impl<K: PrimInt, V: Clone> Clone for CritBit<K, V> {
+    fn clone(&self) -> Self {
+        CritBit {
+            nodes: self.nodes.clone(),
+            root: self.root,
+        }
+    }
+}
+
+impl<K: PrimInt + Debug, V: Debug> Debug for CritBit<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: PrimInt, V: PartialEq> PartialEq for CritBit<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<K: PrimInt, V: Eq> Eq for CritBit<K, V> {}
+
+impl<K: PrimInt + Hash, V: Hash> Hash for CritBit<K, V> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.len());
+        for (k, v) in self {
+            k.hash(state);
+            v.hash(state);
+        }
+    }
+}
+
+impl<K: PrimInt, V: PartialOrd> PartialOrd for CritBit<K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<K: PrimInt, V: Ord> Ord for CritBit<K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<K: PrimInt, V> Index<&K> for CritBit<K, V> {
+    type Output = V;
+
+    fn index(&self, key: &K) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+impl<K: PrimInt, V> IndexMut<&K> for CritBit<K, V> {
+    fn index_mut(&mut self, key: &K) -> &mut V {
+        self.get_mut(key).expect("no entry found for key")
+    }
+}
+
+// Built by sorting rather than inserting, which both takes less time and lays
+// the arena out in key order; the later of two equal keys wins, as it would
+// by inserting.
+impl<K: PrimInt, V> FromIterator<(K, V)> for CritBit<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> CritBit<K, V> {
+        CritBit::from_iter_with(iter, |_, _, later| later)
+    }
+}
+
+impl<K: PrimInt, V> From<BTreeMap<K, V>> for CritBit<K, V> {
+    fn from(map: BTreeMap<K, V>) -> CritBit<K, V> {
+        CritBit::from_sorted_iter(map)
+    }
+}
+
+impl<K: PrimInt, V> From<CritBit<K, V>> for BTreeMap<K, V> {
+    fn from(tree: CritBit<K, V>) -> BTreeMap<K, V> {
+        tree.into_iter().collect()
+    }
+}
+
+// A handful of new pairs are inserted one by one, but anything more is merged
+// with the existing entries into a fresh arena, leaving it in key order just
+// as `FromIterator` does. Both sides are already sorted, so the sort merging
+// them is linear.
+impl<K: PrimInt, V> Extend<(K, V)> for CritBit<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        let incoming: CritBit<K, V> = iter.into_iter().collect();
+        if self.few(incoming.len()) {
+            for (k, v) in incoming {
+                self.insert(k, v);
+            }
+        } else if self.is_empty() {
+            *self = incoming;
+        } else {
+            let ours = core::mem::take(self);
+            *self = CritBit::from_iter_with(ours.into_iter().chain(incoming), |_, _, later| later);
+        }
+    }
+}
+
+impl<K: PrimInt, V> IntoIterator for CritBit<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        IntoIter::new(self)
+    }
+}
+
+impl<'a, K: PrimInt, V> IntoIterator for &'a CritBit<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'a, K: PrimInt, V> IntoIterator for &'a mut CritBit<K, V> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+impl<K: PrimInt, V> CritBitNode<K, V> {
+    fn len(&self) -> usize {
+        match *self {
+            CritBitNode::Leaf(..) => 1,
+            CritBitNode::Internal(InternalCritBitNode { size, .. }) => size as usize,
+        }
+    }
+}
+
+// Arena plumbing. Subtrees are named by the index of their top node, and every
+// index handed to these is expected to be live.
+impl<K: PrimInt, V> CritBit<K, V> {
+    // Borrowed lookups still walk the tree by `K`'s bits. A `K` can only
+    // borrow as a `Q` that holds the same value, so the cast never fails for a
+    // key that could be stored.
+    fn owned_key<Q: PrimInt>(key: &Q) -> Option<K>
+    where
+        K: Borrow<Q>,
+    {
+        <K as num::NumCast>::from(*key)
+    }
+
+    fn root(&self) -> Option<u32> {
+        if self.nodes.is_empty() {
+            None
+        } else {
+            Some(self.root)
+        }
+    }
+
+    fn push(&mut self, node: CritBitNode<K, V>) -> u32 {
+        let at = u32::try_from(self.nodes.len()).expect("CritBit can't index any more nodes");
+        self.nodes.push(node);
+        at
+    }
+
+    fn join(&mut self, left: u32, right: u32, crit: u32) -> u32 {
+        debug_assert!(crit < Self::key_bits(), "crit bit {} is out of range", crit);
+        let size = (self.size(left) + self.size(right)) as u32;
+        self.push(CritBitNode::Internal(InternalCritBitNode {
+            left,
+            right,
+            crit,
+            size,
+        }))
+    }
+
+    // Points whatever link led to `parent`'s `side` (or the root, for `None`)
+    // at `to` instead.
+    fn relink(&mut self, parent: Option<(u32, bool)>, to: u32) {
+        match parent {
+            Some((at, true)) => self.internal_mut(at).right = to,
+            Some((at, false)) => self.internal_mut(at).left = to,
+            None => self.root = to,
+        }
+    }
+
+    // Takes the node at `at` out of the arena, once nothing links to it. The
+    // last node moves into the hole, and the one link to that is redirected.
+    fn release(&mut self, at: u32) -> CritBitNode<K, V> {
+        let node = self.nodes.swap_remove(at as usize);
+        let moved = self.nodes.len() as u32;
+        if at == moved {
+            return node;
+        }
+        if self.root == moved {
+            self.root = at;
+            return node;
+        }
+        // Any key below the moved node leads down to it.
+        let key = *self.leaf(self.edge(at, false)).0;
+        let mut parent = self.root;
+        loop {
+            let internal = self.internal_mut(parent);
+            let next = if bit_at(&key, &internal.crit) {
+                &mut internal.right
+            } else {
+                &mut internal.left
+            };
+            if *next == moved {
+                *next = at;
+                return node;
+            }
+            parent = *next;
+        }
+    }
+
+    // Takes the entries ranked `lo..hi` out of the tree, handing back every
+    // node that goes with them: their leaves and the internal nodes above.
+    fn take_ranks(&mut self, lo: usize, hi: usize) -> Vec<CritBitNode<K, V>> {
+        let Some(root) = self.root() else {
+            return Vec::new();
+        };
+        let (mut detached, mut dead) = (Vec::new(), Vec::new());
+        match self.cut(root, lo, hi, &mut detached, &mut dead) {
+            Some(rest) => self.root = rest,
+            None => return self.nodes.drain(..).collect(),
+        }
+        while let Some(at) = detached.pop() {
+            dead.push(at);
+            if let CritBitNode::Internal(InternalCritBitNode { left, right, .. }) =
+                self.nodes[at as usize]
+            {
+                detached.extend([left, right]);
+            }
+        }
+        self.evict(dead)
+    }
+
+    // Builds a tree in an arena of its own from nodes `take_ranks` handed back.
+    fn from_taken(nodes: Vec<CritBitNode<K, V>>) -> CritBit<K, V> {
+        let mut entries: Vec<(K, V)> = nodes
+            .into_iter()
+            .filter_map(|node| match node {
+                CritBitNode::Leaf(k, v) => Some((k, v)),
+                CritBitNode::Internal(..) => None,
+            })
+            .collect();
+        entries.sort_unstable_by_key(|&(k, _)| k);
+        Self::from_sorted_iter(entries)
+    }
+
+    // Cuts the entries ranked `lo..hi` within the subtree at `at` loose and
+    // returns what's left of it. Only subtrees straddling an end of the span
+    // are descended into: the top of one lying wholly inside goes on
+    // `detached`, and an internal node left with one child goes on `dead`
+    // and is replaced by that child.
+    fn cut(
+        &mut self,
+        at: u32,
+        lo: usize,
+        hi: usize,
+        detached: &mut Vec<u32>,
+        dead: &mut Vec<u32>,
+    ) -> Option<u32> {
+        let size = self.size(at);
+        if lo >= hi.min(size) {
+            return Some(at);
+        }
+        if lo == 0 && hi >= size {
+            detached.push(at);
+            return None;
+        }
+        let (left, right) = (self.child(at, false), self.child(at, true));
+        let below = self.size(left);
+        let left = self.cut(left, lo, hi.min(below), detached, dead);
+        let right = self.cut(
+            right,
+            lo.saturating_sub(below),
+            hi.saturating_sub(below),
+            detached,
+            dead,
+        );
+        match (left, right) {
+            (Some(left), Some(right)) => {
+                let size = (self.size(left) + self.size(right)) as u32;
+                let internal = self.internal_mut(at);
+                (internal.left, internal.right, internal.size) = (left, right, size);
+                Some(at)
+            }
+            (Some(kept), None) | (None, Some(kept)) => {
+                dead.push(at);
+                Some(kept)
+            }
+            (None, None) => unreachable!("A subtree wholly inside the span is cut off whole..."),
+        }
+    }
+
+    // Takes the nodes at `dead`, which nothing live links to any more, out of
+    // the arena. A few are released one at a time, highest index first so
+    // that the node moved into each hole is always a live one; beyond that
+    // it's cheaper to close every hole up in one pass and renumber the links.
+    fn evict(&mut self, mut dead: Vec<u32>) -> Vec<CritBitNode<K, V>> {
+        if self.few(dead.len()) {
+            dead.sort_unstable_by(|a, b| b.cmp(a));
+            return dead.into_iter().map(|at| self.release(at)).collect();
+        }
+        let mut is_dead = alloc::vec![false; self.nodes.len()];
+        for &at in &dead {
+            is_dead[at as usize] = true;
+        }
+        let mut renumber = Vec::with_capacity(is_dead.len());
+        let mut live = 0u32;
+        for &gone in &is_dead {
+            renumber.push(live);
+            live += u32::from(!gone);
+        }
+        let mut at = 0;
+        let evicted = self
+            .nodes
+            .extract_if(.., |_| {
+                at += 1;
+                is_dead[at - 1]
+            })
+            .collect();
+        for node in &mut self.nodes {
+            if let CritBitNode::Internal(internal) = node {
+                internal.left = renumber[internal.left as usize];
+                internal.right = renumber[internal.right as usize];
+            }
+        }
+        self.root = renumber[self.root as usize];
+        evicted
+    }
+
+    fn size(&self, at: u32) -> usize {
+        self.nodes[at as usize].len()
+    }
+
+    fn child(&self, at: u32, right: bool) -> u32 {
+        match self.nodes[at as usize] {
+            CritBitNode::Internal(InternalCritBitNode { right: kid, .. }) if right => kid,
+            CritBitNode::Internal(InternalCritBitNode { left: kid, .. }) => kid,
+            CritBitNode::Leaf(..) => unreachable!("Only internal nodes have children..."),
+        }
+    }
+
+    fn internal_mut(&mut self, at: u32) -> &mut InternalCritBitNode {
+        match self.nodes[at as usize] {
+            CritBitNode::Internal(ref mut internal) => internal,
+            CritBitNode::Leaf(..) => unreachable!("Only internal nodes have children..."),
+        }
+    }
+
+    fn leaf(&self, at: u32) -> (&K, &V) {
+        match self.nodes[at as usize] {
+            CritBitNode::Leaf(ref k, ref v) => (k, v),
+            CritBitNode::Internal(..) => unreachable!("Only leaves carry values..."),
+        }
+    }
+
+    fn leaf_mut(&mut self, at: u32) -> (&K, &mut V) {
+        match self.nodes[at as usize] {
+            CritBitNode::Leaf(ref k, ref mut v) => (k, v),
+            CritBitNode::Internal(..) => unreachable!("Only leaves carry values..."),
+        }
+    }
+
+    fn value_mut(&mut self, at: u32) -> &mut V {
+        self.leaf_mut(at).1
+    }
+
+    // The leaf just after (or before) the present key `key`.
+    fn neighbour(&self, root: u32, key: &K, forward: bool) -> Option<u32> {
+        let located = self.locate(root, key);
+        let next = if forward {
+            located.upper
+        } else {
+            located.lower
+        };
+        next.map(|next| self.edge(next, !forward))
+    }
+
+    // The leftmost (or rightmost) leaf below `at`.
+    fn edge(&self, mut at: u32, right: bool) -> u32 {
+        while let CritBitNode::Internal(..) = self.nodes[at as usize] {
+            at = self.child(at, right);
+        }
+        at
+    }
+
+    fn best_match(&self, mut at: u32, key: &K) -> u32 {
+        while let CritBitNode::Internal(InternalCritBitNode {
+            left, right, crit, ..
+        }) = self.nodes[at as usize]
+        {
+            at = if bit_at(key, &crit) { right } else { left };
+        }
+        at
+    }
+
+    // Descends towards `key` until reaching the subtree whose keys all agree
+    // with it above the critical bit, remembering the nearest branch skipped
+    // on each side. `above` tells whether `key` sorts after that subtree, and
+    // is `None` when `key` is present. `before` counts the keys in every
+    // branch skipped on the left.
+    fn locate(&self, root: u32, key: &K) -> Located {
+        let crit = common_prefix_len(self.leaf(self.best_match(root, key)).0, key);
+        let (mut lower, mut upper, mut before) = (None, None, 0);
+        let mut at = root;
+        loop {
+            match self.nodes[at as usize] {
+                CritBitNode::Internal(InternalCritBitNode {
+                    left,
+                    right,
+                    crit: c,
+                    ..
+                }) if c < crit => {
+                    if bit_at(key, &c) {
+                        lower = Some(left);
+                        before += self.size(left);
+                        at = right;
+                    } else {
+                        upper = Some(right);
+                        at = left;
+                    }
+                }
+                _ => {
+                    let above = if crit == Self::key_bits() {
+                        None
+                    } else {
+                        Some(bit_at(key, &crit))
+                    };
+                    return Located {
+                        subtree: at,
+                        above,
+                        lower,
+                        upper,
+                        before,
+                    };
+                }
+            }
+        }
+    }
+
+    // The subtree holding exactly the keys that agree with `prefix` in their
+    // top `prefix_bits` bits, if there are any.
+    fn prefix_subtree(&self, root: u32, prefix: &K, prefix_bits: u32) -> Option<u32> {
+        let prefix_bits = prefix_bits.min(Self::key_bits());
+        let mut at = root;
+        while let CritBitNode::Internal(InternalCritBitNode {
+            left, right, crit, ..
+        }) = self.nodes[at as usize]
+        {
+            if crit >= prefix_bits {
+                break;
+            }
+            at = if bit_at(prefix, &crit) { right } else { left };
+        }
+        if common_prefix_len(self.leaf(self.edge(at, false)).0, prefix) >= prefix_bits {
+            Some(at)
+        } else {
+            None
+        }
+    }
+
+    // Returns the leftmost key and the number of leaves below `at`.
+    fn check(&self, at: u32, parent: Option<u32>) -> Result<(K, usize), String> {
+        let InternalCritBitNode {
+            left,
+            right,
+            crit,
+            size,
+        } = match self.nodes.get(at as usize) {
+            Some(&CritBitNode::Leaf(k, _)) => return Ok((k, 1)),
+            Some(&CritBitNode::Internal(internal)) => internal,
+            None => return Err(format!("node index {at} is outside the arena")),
+        };
+        if crit >= Self::key_bits() {
+            return Err(format!("crit bit {crit} is past the end of the key"));
+        }
+        if let Some(parent) = parent
+            && parent >= crit
+        {
+            return Err(format!("crit bit {crit} sits below crit bit {parent}"));
+        }
+        let (left_min, left_len) = self.check(left, Some(crit))?;
+        let (right_min, right_len) = self.check(right, Some(crit))?;
+        if bit_at(&left_min, &crit) || !bit_at(&right_min, &crit) {
+            return Err(format!("keys on the wrong side of crit bit {crit}"));
+        }
+        if common_prefix_len(&left_min, &right_min) != crit {
+            return Err(format!("branches below crit bit {crit} disagree above it"));
+        }
+        if size as usize != left_len + right_len {
+            return Err(format!(
+                "internal node at crit bit {crit} caches size {size} but holds {}",
+                left_len + right_len
+            ));
+        }
+        Ok((left_min, size as usize))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        CritBit, CritBitNode, DepthExceeded, Entry, InternalCritBitNode, Iter, bit_at,
+        common_prefix_len,
+    };
+    use num::PrimInt;
+    use std::ops::Bound;
+
+    #[test]
+    fn verify_bit_at() {
+        assert!(!bit_at(&1u8, &0u32));
+        assert!(bit_at(&128u8, &0u32));
+        assert!(bit_at(&1u8, &7u32));
+        assert!(!bit_at(&128u8, &7u32));
+    }
+
+    #[test]
+    fn bit_at_out_of_range() {
+        for x in 0..=255u8 {
+            assert!(!bit_at(&x, &8u32));
+            assert!(!bit_at(&x, &9u32));
+            assert!(!bit_at(&x, &u32::MAX));
+        }
+        assert!(bit_at(&1u64, &63u32));
+        assert!(!bit_at(&u64::MAX, &64u32));
+    }
+
+    #[test]
+    fn empty_len() {
+        let t: CritBit<u8, ()> = CritBit::new();
+        assert_eq!(t.len(), 0);
+    }
+
+    #[test]
+    fn empty_contains_key() {
+        let t: CritBit<u8, ()> = CritBit::new();
+        assert!(!t.contains_key(&0u8));
+        assert!(!t.contains_key(&128u8));
+        assert!(!t.contains_key(&255u8));
+    }
+
+    #[test]
+    fn empty_get() {
+        let t: CritBit<u8, ()> = CritBit::new();
+        assert_eq!(t.get(&0u8), None);
+        assert_eq!(t.get(&128u8), None);
+        assert_eq!(t.get(&255u8), None);
+    }
+
+    #[test]
+    fn empty_get_mut() {
+        let mut t: CritBit<u8, ()> = CritBit::new();
+        assert!(t.get_mut(&0u8).is_none());
+        assert!(t.get_mut(&128u8).is_none());
+        assert!(t.get_mut(&255u8).is_none());
+    }
+
+    #[test]
+    fn insert_len() {
+        let mut t: CritBit<u8, ()> = CritBit::new();
+        assert_eq!(t.len(), 0);
+
+        t.insert(0u8, ());
+        assert_eq!(t.len(), 1)
+    }
+
+    #[test]
+    fn insert_contains_key() {
+        let mut t: CritBit<u8, ()> = CritBit::new();
+        assert!(!t.contains_key(&0u8));
+
+        t.insert(0u8, ());
+        assert!(t.contains_key(&0u8));
+    }
+
+    #[test]
+    fn insert_get() {
+        let mut t: CritBit<u8, u8> = CritBit::new();
+        assert_eq!(t.get(&0u8), None);
+
+        t.insert(0u8, 1u8);
+        assert_eq!(t.get(&0u8), Some(&1u8));
+    }
+
+    #[test]
+    fn insert_get_mut() {
+        let mut t: CritBit<u8, u8> = CritBit::new();
+        assert_eq!(t.get(&0u8), None);
+
+        t.insert(0u8, 1u8);
+        assert_eq!(t.get(&0u8), Some(&1u8));
+
+        *t.get_mut(&0u8).unwrap() = 2u8;
+        assert_eq!(t.get(&0u8), Some(&2u8));
+    }
+
+    #[test]
+    fn insert_insert() {
+        let mut t: CritBit<u8, u8> = CritBit::new();
+        assert_eq!(t.get(&0u8), None);
+
+        t.insert(0u8, 1u8);
+        assert_eq!(t.get(&0u8), Some(&1u8));
+
+        assert_eq!(t.insert(0u8, 2u8), Some(1u8));
+        assert_eq!(t.get(&0u8), Some(&2u8));
+    }
+
+    #[test]
+    fn remove_absent() {
+        let mut t: CritBit<u8, u8> = CritBit::new();
+        assert_eq!(t.remove(&0u8), None);
+
+        t.insert(0u8, 1u8);
+        assert_eq!(t.remove(&1u8), None);
+        assert_eq!(t.len(), 1);
+    }
+
+    #[test]
+    fn remove_root() {
+        let mut t: CritBit<u8, u8> = CritBit::new();
+        t.insert(0u8, 1u8);
+
+        assert_eq!(t.remove(&0u8), Some(1u8));
+        assert_eq!(t.len(), 0);
+        assert!(t.is_empty());
+        assert_eq!(t.get(&0u8), None);
+    }
+
+    #[test]
+    fn remove_left() {
+        let mut t: CritBit<u8, u8> = CritBit::new();
+        t.insert(1u8, 1u8);
+        t.insert(128u8, 2u8);
+
+        assert_eq!(t.remove(&1u8), Some(1u8));
+        assert_eq!(t.len(), 1);
+        assert_eq!(t.get(&1u8), None);
+        assert_eq!(t.get(&128u8), Some(&2u8));
+    }
+
+    #[test]
+    fn remove_right() {
+        let mut t: CritBit<u8, u8> = CritBit::new();
+        t.insert(1u8, 1u8);
+        t.insert(128u8, 2u8);
+
+        assert_eq!(t.remove(&128u8), Some(2u8));
+        assert_eq!(t.len(), 1);
+        assert_eq!(t.get(&128u8), None);
+        assert_eq!(t.get(&1u8), Some(&1u8));
+    }
+
+    #[test]
+    fn remove_nested() {
+        let mut t: CritBit<u8, u8> = CritBit::new();
+        t.insert(0u8, 0u8);
+        t.insert(128u8, 1u8);
+        t.insert(129u8, 2u8);
+        assert_eq!(t.len(), 3);
+
+        assert_eq!(t.remove(&129u8), Some(2u8));
+        assert_eq!(t.len(), 2);
+        assert_eq!(t.get(&129u8), None);
+        assert_eq!(t.get(&128u8), Some(&1u8));
+
+        assert_eq!(t.remove(&0u8), Some(0u8));
+        assert_eq!(t.len(), 1);
+        assert_eq!(t.get(&0u8), None);
+
+        assert_eq!(t.remove(&128u8), Some(1u8));
+        assert!(t.is_empty());
+    }
+
+    fn assert_well_formed<K: PrimInt, V>(t: &CritBit<K, V>) {
+        fn walk<K: PrimInt, V>(
+            t: &CritBit<K, V>,
+            at: u32,
+            path: &[(u32, bool)],
+            seen: &mut [bool],
+        ) {
+            assert!(!seen[at as usize], "node {at} is reached twice");
+            seen[at as usize] = true;
+            match t.nodes[at as usize] {
+                CritBitNode::Leaf(ref k, _) => {
+                    for &(crit, side) in path {
+                        assert_eq!(bit_at(k, &crit), side);
+                    }
+                }
+                CritBitNode::Internal(InternalCritBitNode {
+                    left,
+                    right,
+                    crit,
+                    size,
+                }) => {
+                    if let Some(&(parent, _)) = path.last() {
+                        assert!(parent < crit);
+                    }
+                    assert_eq!(
+                        size as usize,
+                        Iter::new(&t.nodes, Some(left)).count()
+                            + Iter::new(&t.nodes, Some(right)).count()
+                    );
+                    walk(t, left, &[path, &[(crit, false)]].concat(), seen);
+                    walk(t, right, &[path, &[(crit, true)]].concat(), seen);
+                }
+            }
+        }
+        if let Some(root) = t.root() {
+            let mut seen = vec![false; t.nodes.len()];
+            walk(t, root, &[], &mut seen);
+            assert!(seen.iter().all(|&s| s), "the arena holds unreachable nodes");
+        }
+    }
+
+    #[test]
+    fn insert_above_internal() {
+        let mut t: CritBit<u8, u8> = CritBit::new();
+        t.insert(0b0000_0001u8, 0u8);
+        t.insert(0b0000_0010u8, 1u8);
+        t.insert(0b1000_0000u8, 2u8);
+        assert_well_formed(&t);
+
+        assert_eq!(t.len(), 3);
+        assert_eq!(t.get(&0b0000_0001u8), Some(&0u8));
+        assert_eq!(t.get(&0b0000_0010u8), Some(&1u8));
+        assert_eq!(t.get(&0b1000_0000u8), Some(&2u8));
+    }
+
+    #[test]
+    fn insert_orderings() {
+        let keys = [
+            0b1000_0000u8,
+            0b0000_0001,
+            0b0100_0000,
+            0b0000_0011,
+            0b1100_0000,
+        ];
+        for rotation in 0..keys.len() {
+            let mut t: CritBit<u8, usize> = CritBit::new();
+            for (i, k) in keys
+                .iter()
+                .cycle()
+                .skip(rotation)
+                .take(keys.len())
+                .enumerate()
+            {
+                assert_eq!(t.insert(*k, i), None);
+                assert_well_formed(&t);
+            }
+            assert_eq!(t.len(), keys.len());
+            for k in keys.iter() {
+                assert!(t.contains_key(k));
+            }
+            for (i, k) in keys.iter().enumerate() {
+                t.remove(k);
+                assert_well_formed(&t);
+                assert_eq!(t.len(), keys.len() - i - 1);
+            }
+        }
+    }
+
+    #[test]
+    fn insert_replace_nested() {
+        let mut t: CritBit<u8, u8> = CritBit::new();
+        t.insert(0b0000_0001u8, 0u8);
+        t.insert(0b0000_0010u8, 1u8);
+        t.insert(0b1000_0000u8, 2u8);
+
+        assert_eq!(t.insert(0b0000_0010u8, 3u8), Some(1u8));
+        assert_eq!(t.get(&0b0000_0010u8), Some(&3u8));
+        assert_eq!(t.len(), 3);
+        assert_well_formed(&t);
+    }
+
+    #[test]
+    fn empty_iter() {
+        let t: CritBit<u8, ()> = CritBit::new();
+        assert_eq!(t.iter().next(), None);
+    }
+
+    #[test]
+    fn iter_sorted() {
+        let mut t: CritBit<u16, u16> = CritBit::new();
+        for i in 0..1000u16 {
+            let k = i.wrapping_mul(40503) ^ 0x5a5a;
+            t.insert(k, !k);
+        }
+
+        let mut expected: Vec<u16> = (0..1000u16)
+            .map(|i| i.wrapping_mul(40503) ^ 0x5a5a)
+            .collect();
+        expected.sort();
+        let keys: Vec<u16> = t.iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, expected);
+        assert!(t.iter().all(|(k, v)| *v == !*k));
+    }
+
+    #[test]
+    fn iter_mut_update() {
+        let keys = [7u32, 1 << 31, 3, 1 << 20, 0, 42, u32::MAX];
+        let mut t: CritBit<u32, u32> = CritBit::new();
+        for k in keys.iter() {
+            t.insert(*k, *k / 2);
+        }
+
+        let mut seen = Vec::new();
+        for (k, v) in t.iter_mut() {
+            seen.push(*k);
+            *v *= 2;
+        }
+
+        let mut expected = keys.to_vec();
+        expected.sort();
+        assert_eq!(seen, expected);
+        for k in keys.iter() {
+            assert_eq!(t.get(k), Some(&(*k / 2 * 2)));
+        }
+    }
+
+    #[test]
+    fn into_iter_sorted() {
+        let mut t: CritBit<u16, String> = CritBit::new();
+        for i in 0..500u16 {
+            let k = i.wrapping_mul(40503);
+            t.insert(k, k.to_string());
+        }
+
+        let pairs: Vec<(u16, String)> = t.into_iter().collect();
+        assert_eq!(pairs.len(), 500);
+        assert!(pairs.windows(2).all(|w| w[0].0 < w[1].0));
+        assert!(pairs.iter().all(|(k, v)| *v == k.to_string()));
+    }
+
+    #[test]
+    fn into_iter_partial() {
+        let mut t: CritBit<u8, Box<u8>> = CritBit::new();
+        for k in [3u8, 1, 2, 200, 100] {
+            t.insert(k, Box::new(k));
+        }
+
+        let mut it = t.into_iter();
+        assert_eq!(it.next(), Some((1u8, Box::new(1u8))));
+        assert_eq!(it.next(), Some((2u8, Box::new(2u8))));
+    }
+
+    #[test]
+    fn keys_values() {
+        let mut t: CritBit<u16, u16> = CritBit::new();
+        for k in [900u16, 3, 65535, 40, 0, 1024] {
+            t.insert(k, k.wrapping_add(1));
+        }
+
+        let keys: Vec<u16> = t.keys().copied().collect();
+        assert_eq!(keys, vec![0, 3, 40, 900, 1024, 65535]);
+
+        for v in t.values_mut() {
+            *v = v.wrapping_sub(1);
+        }
+        let values: Vec<u16> = t.values().copied().collect();
+        assert_eq!(values, keys);
+    }
+
+    #[test]
+    fn collect_last_wins() {
+        let pairs = vec![
+            (5u32, "five".to_string()),
+            (1u32, "one".to_string()),
+            (5u32, "FIVE".to_string()),
+        ];
+        let t: CritBit<u32, String> = pairs.into_iter().collect();
+
+        assert_eq!(t.len(), 2);
+        assert_eq!(t.get(&1u32).map(String::as_str), Some("one"));
+        assert_eq!(t.get(&5u32).map(String::as_str), Some("FIVE"));
+    }
+
+    #[test]
+    fn extend_overwrites() {
+        let mut t: CritBit<u32, u32> = vec![(1u32, 1u32), (2, 2), (3, 3)].into_iter().collect();
+        t.extend(vec![(3u32, 30u32), (4, 40), (1, 10)]);
+
+        assert_eq!(t.len(), 4);
+        assert_eq!(t.get(&1u32), Some(&10u32));
+        assert_eq!(t.get(&2u32), Some(&2u32));
+        assert_eq!(t.get(&3u32), Some(&30u32));
+        assert_eq!(t.get(&4u32), Some(&40u32));
+    }
+
+    #[test]
+    fn bulk_builds_lay_out_in_key_order() {
+        fn leaves_in_key_order(t: &CritBit<u16, u16>) -> bool {
+            let leaves = t.nodes.iter().filter_map(|node| match node {
+                CritBitNode::Leaf(k, _) => Some(k),
+                CritBitNode::Internal(..) => None,
+            });
+            leaves.eq(t.keys())
+        }
+
+        let scrambled = |n: u16| (0..n).map(|i| (i.wrapping_mul(40_503), i));
+        let mut t: CritBit<u16, u16> = scrambled(1000).collect();
+        assert_well_formed(&t);
+        assert!(leaves_in_key_order(&t));
+
+        // A few more go in one at a time, in place.
+        t.extend(scrambled(1002).skip(1000));
+        assert_well_formed(&t);
+        assert_eq!(t.len(), 1002);
+        assert!(!leaves_in_key_order(&t));
+
+        // Many more are merged in, rebuilding the arena in order again.
+        t.extend(scrambled(2000).map(|(k, v)| (k, v + 1)));
+        assert_well_formed(&t);
+        assert!(leaves_in_key_order(&t));
+        assert_eq!(t.len(), 2000);
+        assert!(scrambled(2000).all(|(k, v)| t[&k] == v + 1));
+    }
+
+    #[test]
+    fn empty_min_max() {
+        let t: CritBit<u16, ()> = CritBit::new();
+        assert_eq!(t.min_key(), None);
+        assert_eq!(t.max_key(), None);
+        assert_eq!(t.first(), None);
+        assert_eq!(t.last(), None);
+    }
+
+    #[test]
+    fn min_max() {
+        let mut t: CritBit<u16, u16> = CritBit::new();
+        for i in 1..2000u16 {
+            let k = i.wrapping_mul(40503) % 60000 + 17;
+            t.insert(k, !k);
+        }
+        let min = *t.keys().min().unwrap();
+        let max = *t.keys().max().unwrap();
+
+        assert_eq!(t.min_key(), Some(&min));
+        assert_eq!(t.max_key(), Some(&max));
+        assert_eq!(t.first(), Some((&min, &!min)));
+        assert_eq!(t.last(), Some((&max, &!max)));
+    }
+
+    #[test]
+    fn range_bounds() {
+        let t: CritBit<u32, u32> = (0..100u32).map(|k| (k, k * 2)).collect();
+
+        let inclusive: Vec<u32> = t.range(20..=30).map(|(k, _)| *k).collect();
+        assert_eq!(inclusive, (20..=30).collect::<Vec<u32>>());
+        assert!(t.range(20..=30).all(|(k, v)| *v == *k * 2));
+
+        let exclusive: Vec<u32> = t.range(20..30).map(|(k, _)| *k).collect();
+        assert_eq!(exclusive, (20..30).collect::<Vec<u32>>());
+
+        let from: Vec<u32> = t.range(95..).map(|(k, _)| *k).collect();
+        assert_eq!(from, vec![95, 96, 97, 98, 99]);
+
+        let to: Vec<u32> = t.range(..3).map(|(k, _)| *k).collect();
+        assert_eq!(to, vec![0, 1, 2]);
+
+        assert_eq!(t.range(..).count(), 100);
+        assert_eq!(t.range(100..).count(), 0);
+
+        let open: Vec<u32> = t
+            .range((Bound::Excluded(10), Bound::Excluded(13)))
+            .map(|(k, _)| *k)
+            .collect();
+        assert_eq!(open, vec![11, 12]);
+    }
+
+    #[test]
+    fn range_sparse() {
+        let keys = [3u32, 17, 64, 65, 1000, 1 << 20, u32::MAX];
+        let t: CritBit<u32, ()> = keys.iter().map(|k| (*k, ())).collect();
+
+        for lo in [0u32, 3, 4, 63, 64, 66, 999, 1001, 1 << 21] {
+            for hi in [0u32, 17, 65, 1000, u32::MAX] {
+                let got: Vec<u32> = t.range(lo..=hi).map(|(k, _)| *k).collect();
+                let want: Vec<u32> = keys
+                    .iter()
+                    .copied()
+                    .filter(|k| lo <= *k && *k <= hi)
+                    .collect();
+                assert_eq!(got, want);
+            }
+        }
+    }
+
+    #[test]
+    fn empty_floor_ceiling() {
+        let t: CritBit<u8, ()> = CritBit::new();
+        assert_eq!(t.floor(&0u8), None);
+        assert_eq!(t.ceiling(&0u8), None);
+    }
+
+    #[test]
+    fn floor_ceiling() {
+        let t: CritBit<u8, u8> = [10u8, 20, 30, 128, 200].iter().map(|k| (*k, !*k)).collect();
+
+        assert_eq!(t.floor(&5u8), None);
+        assert_eq!(t.ceiling(&5u8), Some((&10u8, &!10u8)));
+        assert_eq!(t.floor(&201u8), Some((&200u8, &!200u8)));
+        assert_eq!(t.ceiling(&201u8), None);
+        assert_eq!(t.floor(&30u8), Some((&30u8, &!30u8)));
+        assert_eq!(t.ceiling(&30u8), Some((&30u8, &!30u8)));
+
+        for q in 0..=255u8 {
+            assert_eq!(t.floor(&q), t.range(..=q).last());
+            assert_eq!(t.ceiling(&q), t.range(q..).next());
+        }
+    }
+
+    #[test]
+    fn entry_counting() {
+        let mut t: CritBit<u32, u32> = CritBit::new();
+        for k in [3u32, 1, 3, 7, 3, 1, 1 << 31] {
+            *t.entry(k).or_insert(0) += 1;
+        }
+
+        assert_eq!(t.len(), 4);
+        assert_eq!(t.get(&1u32), Some(&2u32));
+        assert_eq!(t.get(&3u32), Some(&3u32));
+        assert_eq!(t.get(&7u32), Some(&1u32));
+        assert_eq!(t.get(&(1u32 << 31)), Some(&1u32));
+    }
+
+    #[test]
+    fn entry_variants() {
+        let mut t: CritBit<u8, String> = CritBit::new();
+        t.insert(1u8, "one".to_string());
+
+        match t.entry(1u8) {
+            Entry::Occupied(mut e) => {
+                assert_eq!(*e.key(), 1u8);
+                assert_eq!(e.get(), "one");
+                assert_eq!(e.insert("uno".to_string()), "one");
+            }
+            Entry::Vacant(_) => panic!("expected an occupied entry"),
+        }
+        match t.entry(2u8) {
+            Entry::Vacant(e) => {
+                assert_eq!(*e.key(), 2u8);
+                e.insert("two".to_string()).push('!');
+            }
+            Entry::Occupied(_) => panic!("expected a vacant entry"),
+        }
+        assert_eq!(t.get(&1u8).map(String::as_str), Some("uno"));
+        assert_eq!(t.get(&2u8).map(String::as_str), Some("two!"));
+
+        t.entry(2u8)
+            .and_modify(|v| v.push('?'))
+            .or_insert_with(String::new);
+        t.entry(3u8)
+            .and_modify(|v| v.push('?'))
+            .or_insert_with(|| "three".to_string());
+        assert_eq!(t.get(&2u8).map(String::as_str), Some("two!?"));
+        assert_eq!(t.get(&3u8).map(String::as_str), Some("three"));
+        assert_eq!(t.len(), 3);
+    }
+
+    #[test]
+    fn retain_even() {
+        let mut t: CritBit<u16, u16> = (0..50u16).map(|k| (k, k)).collect();
+        t.retain(|k, v| {
+            *v += 1;
+            *k % 2 == 0
+        });
+        assert_well_formed(&t);
+
+        assert_eq!(t.len(), 25);
+        for k in 0..50u16 {
+            assert_eq!(
+                t.get(&k).copied(),
+                if k % 2 == 0 { Some(k + 1) } else { None }
+            );
+        }
+    }
+
+    #[test]
+    fn retain_none() {
+        let mut t: CritBit<u16, ()> = (0..50u16).map(|k| (k, ())).collect();
+        t.retain(|_, _| false);
+        assert!(t.is_empty());
+        assert_eq!(t.len(), 0);
+    }
+
+    #[test]
+    fn retain_almost_all() {
+        let mut t: CritBit<u16, u16> = (0..1000u16).map(|k| (k.wrapping_mul(40_503), k)).collect();
+        t.retain(|_, v| {
+            *v += 1;
+            true
+        });
+        assert_eq!(t.len(), 1000);
+        assert!(t.iter().all(|(k, v)| *k == (*v - 1).wrapping_mul(40_503)));
+
+        t.retain(|_, v| *v % 100 != 0);
+        assert_well_formed(&t);
+        assert_eq!(t.len(), 990);
+        assert!(t.values().all(|v| v % 100 != 0));
+    }
+
+    #[test]
+    fn clone_independent() {
+        let mut t: CritBit<u32, String> = (0..100u32).map(|k| (k * 7919, k.to_string())).collect();
+        let c = t.clone();
+        assert_well_formed(&c);
+
+        t.get_mut(&0u32).unwrap().push('!');
+        t.insert(1u32, "new".to_string());
+
+        assert_eq!(c.len(), 100);
+        assert_eq!(c.get(&0u32).map(String::as_str), Some("0"));
+        assert_eq!(c.get(&1u32), None);
+        assert!(c.iter().map(|(k, _)| *k).eq((0..100u32).map(|k| k * 7919)));
+        assert!(c.iter().all(|(k, v)| *v == (*k / 7919).to_string()));
+    }
+
+    #[test]
+    fn debug_format() {
+        let mut t: CritBit<u8, &str> = CritBit::new();
+        assert_eq!(format!("{:?}", t), "{}");
+
+        t.insert(200u8, "c");
+        t.insert(1u8, "a");
+        t.insert(20u8, "b");
+        assert_eq!(format!("{:?}", t), r#"{1: "a", 20: "b", 200: "c"}"#);
+    }
+
+    #[test]
+    fn eq_by_contents() {
+        let pairs: Vec<(u16, u16)> = (0..200u16).map(|i| (i.wrapping_mul(40503), i)).collect();
+        let forward: CritBit<u16, u16> = pairs.iter().copied().collect();
+        let backward: CritBit<u16, u16> = pairs.iter().rev().copied().collect();
+        assert_eq!(forward, backward);
+
+        let mut shorter = backward.clone();
+        shorter.remove(&pairs[0].0);
+        assert_ne!(forward, shorter);
+
+        let mut changed = backward.clone();
+        *changed.get_mut(&pairs[0].0).unwrap() += 1;
+        assert_ne!(forward, changed);
+
+        assert_eq!(CritBit::<u8, ()>::new(), CritBit::new());
+    }
+
+    #[test]
+    fn len_interleaved() {
+        let mut t: CritBit<u32, u32> = CritBit::new();
+        let mut expected = 0;
+        for i in 0..300u32 {
+            let k = i.wrapping_mul(2654435761);
+            if t.insert(k, i).is_none() {
+                expected += 1;
+            }
+            assert_eq!(t.insert(k, i), Some(i));
+            if i % 3 == 0 {
+                let gone = (i / 2).wrapping_mul(2654435761);
+                if t.remove(&gone).is_some() {
+                    expected -= 1;
+                }
+                assert_eq!(t.remove(&gone), None);
+            }
+            assert_eq!(t.len(), expected);
+        }
+        assert_well_formed(&t);
+
+        t.retain(|k, _| k % 2 == 0);
+        assert_eq!(t.len(), t.iter().count());
+        assert_well_formed(&t);
+        assert_eq!(t.clone().len(), t.len());
+    }
+
+    #[test]
+    fn drop_large() {
+        let mut t: CritBit<u64, Box<u64>> = CritBit::new();
+        for k in 0..50_000u64 {
+            t.insert(k, Box::new(k));
+        }
+        assert_eq!(t.len(), 50_000);
+        drop(t);
+
+        let mut t: CritBit<u64, Box<u64>> = (0..50_000u64).map(|k| (k, Box::new(k))).collect();
+        t.clear();
+        assert!(t.is_empty());
+
+        let t: CritBit<u64, Box<u64>> = (0..50_000u64).map(|k| (k, Box::new(k))).collect();
+        let mut it = t.into_iter();
+        assert_eq!(it.next(), Some((0u64, Box::new(0u64))));
+        drop(it);
+    }
+
+    #[test]
+    fn longest_prefix_match() {
+        let t: CritBit<u8, u8> = [0b1010_0000u8, 0b1010_1100, 0b0110_0001]
+            .iter()
+            .map(|k| (*k, !*k))
+            .collect();
+
+        assert_eq!(
+            t.longest_prefix_match(&0b1010_1111u8, 6),
+            Some((&0b1010_1100u8, &!0b1010_1100u8))
+        );
+        assert_eq!(t.longest_prefix_match(&0b1010_1111u8, 7), None);
+        assert_eq!(
+            t.longest_prefix_match(&0b0111_1111u8, 3),
+            Some((&0b0110_0001u8, &!0b0110_0001u8))
+        );
+        assert_eq!(t.longest_prefix_match(&0b0111_1111u8, 4), None);
+        assert_eq!(
+            t.longest_prefix_match(&0b1111_1111u8, 1)
+                .map(|(k, _)| k >> 7),
+            Some(1)
+        );
+
+        assert!(t.longest_prefix_match(&0b1111_1111u8, 0).is_some());
+        assert_eq!(
+            t.longest_prefix_match(&0b1010_1100u8, 8),
+            Some((&0b1010_1100u8, &!0b1010_1100u8))
+        );
+        assert_eq!(
+            t.longest_prefix_match(&0b1010_1100u8, 200),
+            Some((&0b1010_1100u8, &!0b1010_1100u8))
+        );
+        assert_eq!(t.longest_prefix_match(&0b1010_1101u8, 200), None);
+
+        let empty: CritBit<u8, u8> = CritBit::new();
+        assert_eq!(empty.longest_prefix_match(&0u8, 0), None);
+    }
+
+    #[test]
+    fn prefix_iter() {
+        let inside = [0b1000_0000u8, 0b1000_0001, 0b1000_0010, 0b1000_0011];
+        let outside = [0b0000_0000u8, 0b1000_0100, 0b1100_0001, 0b1111_1111];
+        let t: CritBit<u8, u8> = inside
+            .iter()
+            .chain(outside.iter())
+            .map(|k| (*k, !*k))
+            .collect();
+
+        let keys: Vec<u8> = t.prefix_iter(0b1000_0011u8, 6).map(|(k, _)| *k).collect();
+        assert_eq!(keys, inside.to_vec());
+        assert!(t.prefix_iter(0b1000_0000u8, 6).all(|(k, v)| *v == !*k));
+
+        assert_eq!(t.prefix_iter(0b0100_0000u8, 2).count(), 0);
+        assert_eq!(t.prefix_iter(0u8, 0).count(), 8);
+        assert_eq!(t.prefix_iter(0b1000_0010u8, 8).count(), 1);
+        assert_eq!(t.prefix_iter(0b1000_0010u8, 99).count(), 1);
+        assert_eq!(t.prefix_iter(0b1000_0111u8, 8).count(), 0);
+        assert_eq!(t.prefix_iter(0b1000_0000u8, 1).count(), 7);
+    }
+
+    #[test]
+    fn get_or_insert_with_lazy() {
+        let mut calls = 0;
+        let mut t: CritBit<u32, Vec<u32>> = CritBit::new();
+        t.insert(1u32, vec![1]);
+
+        t.get_or_insert_with(1u32, || {
+            calls += 1;
+            Vec::new()
+        })
+        .push(2);
+        assert_eq!(calls, 0);
+        assert_eq!(t.get(&1u32), Some(&vec![1, 2]));
+
+        t.get_or_insert_with(9u32, || {
+            calls += 1;
+            vec![9]
+        })
+        .push(10);
+        assert_eq!(calls, 1);
+        assert_eq!(t.get(&9u32), Some(&vec![9, 10]));
+        assert_eq!(t.len(), 2);
+    }
+
+    #[test]
+    fn split_off_middle() {
+        let mut t: CritBit<u8, u8> = (0..20u8).map(|k| (k, k)).collect();
+        let rest = t.split_off(&10u8);
+        assert_well_formed(&t);
+        assert_well_formed(&rest);
+
+        assert!(t.keys().copied().eq(0..10u8));
+        assert!(rest.keys().copied().eq(10..20u8));
+        assert_eq!(t.len(), 10);
+        assert_eq!(rest.len(), 10);
+    }
+
+    #[test]
+    fn split_off_edges() {
+        let keys = [3u8, 17, 64, 65, 130, 255];
+        for at in 0..=255u8 {
+            let mut t: CritBit<u8, ()> = keys.iter().map(|k| (*k, ())).collect();
+            let rest = t.split_off(&at);
+            assert_well_formed(&t);
+            assert_well_formed(&rest);
+            assert!(
+                t.keys()
+                    .copied()
+                    .eq(keys.iter().copied().filter(|k| *k < at))
+            );
+            assert!(
+                rest.keys()
+                    .copied()
+                    .eq(keys.iter().copied().filter(|k| *k >= at))
+            );
+        }
+
+        let mut empty: CritBit<u8, ()> = CritBit::new();
+        assert!(empty.split_off(&0u8).is_empty());
+
+        let mut t: CritBit<u16, ()> = (0..1000u16).map(|k| (k, ())).collect();
+        let rest = t.split_off(&998);
+        assert_well_formed(&t);
+        assert!(rest.keys().copied().eq(998..1000));
+        let rest = t.split_off(&2);
+        assert_well_formed(&rest);
+        assert!(t.keys().copied().eq(0..2));
+        assert!(rest.keys().copied().eq(2..998));
+    }
+
+    #[test]
+    fn append_disjoint() {
+        let mut a: CritBit<u16, u16> = (0..50u16).map(|k| (k * 2, k)).collect();
+        let mut b: CritBit<u16, u16> = (0..50u16).map(|k| (k * 2 + 1, k)).collect();
+        a.append(&mut b);
+        assert_well_formed(&a);
+
+        assert!(b.is_empty());
+        assert_eq!(a.len(), 100);
+        assert!(a.keys().copied().eq(0..100u16));
+        assert!(a.iter().all(|(k, v)| *v == *k / 2));
+    }
+
+    #[test]
+    fn append_overlapping() {
+        let mut a: CritBit<u8, &str> = vec![(1u8, "a1"), (2, "a2"), (200, "a200")]
+            .into_iter()
+            .collect();
+        let mut b: CritBit<u8, &str> = vec![(2u8, "b2"), (3, "b3")].into_iter().collect();
+        a.append(&mut b);
+        assert_well_formed(&a);
+
+        assert!(b.is_empty());
+        assert_eq!(a.len(), 4);
+        assert_eq!(a.get(&2u8), Some(&"b2"));
+        assert_eq!(a.get(&1u8), Some(&"a1"));
+        assert_eq!(a.get(&3u8), Some(&"b3"));
+
+        let mut empty: CritBit<u8, &str> = CritBit::new();
+        empty.append(&mut a);
+        assert!(a.is_empty());
+        assert_eq!(empty.len(), 4);
+    }
+
+    #[test]
+    fn pop_min_max() {
+        let mut t: CritBit<u16, u16> = CritBit::new();
+        for i in 0..300u16 {
+            let k = i.wrapping_mul(40503);
+            t.insert(k, !k);
+        }
+        let mut expected: Vec<u16> = t.keys().copied().collect();
+
+        let mut popped = Vec::new();
+        while let Some((k, v)) = t.pop_min() {
+            assert_eq!(v, !k);
+            popped.push(k);
+            if popped.len() % 50 == 0 {
+                assert_well_formed(&t);
+                assert_eq!(t.len(), 300 - popped.len());
+            }
+        }
+        assert_eq!(popped, expected);
+        assert!(t.is_empty());
+        assert_eq!(t.pop_min(), None);
+
+        let mut t: CritBit<u16, u16> = expected.iter().map(|k| (*k, !*k)).collect();
+        let mut popped = Vec::new();
+        while let Some((k, _)) = t.pop_max() {
+            popped.push(k);
+        }
+        expected.reverse();
+        assert_eq!(popped, expected);
+        assert_eq!(t.pop_max(), None);
+    }
+
+    #[test]
+    fn const_new() {
+        static EMPTY: CritBit<u32, u32> = CritBit::new();
+        const ALSO_EMPTY: CritBit<u8, ()> = CritBit::new();
+
+        assert!(EMPTY.is_empty());
+        assert_eq!(EMPTY.len(), 0);
+        assert_eq!(EMPTY.get(&0u32), None);
+        assert!(ALSO_EMPTY.is_empty());
+    }
+
+    #[test]
+    fn insert_stress() {
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        let mut t: CritBit<u64, u64> = CritBit::new();
+        let mut keys = Vec::with_capacity(100_000);
+        for _ in 0..100_000 {
+            let k = next();
+            t.insert(k, !k);
+            keys.push(k);
+        }
+        keys.sort();
+        keys.dedup();
+
+        assert_eq!(t.len(), keys.len());
+        assert!(t.keys().eq(keys.iter()));
+        assert!(keys.iter().all(|k| t.get(k) == Some(&!*k)));
+        assert_well_formed(&t);
+    }
+
+    #[test]
+    fn contains_value() {
+        let t: CritBit<u8, String> = (0..10u8).map(|k| (k, format!("v{}", k))).collect();
+        assert!(t.contains_value(&"v7".to_string()));
+        assert!(!t.contains_value(&"v10".to_string()));
+        assert!(!CritBit::<u8, u8>::new().contains_value(&0u8));
+    }
+
+    #[test]
+    fn rank() {
+        let t: CritBit<u32, ()> = [0u32, 10, 20, 30].iter().map(|k| (*k, ())).collect();
+        assert_eq!(t.rank(&25u32), 3);
+        assert_eq!(t.rank(&0u32), 0);
+        assert_eq!(t.rank(&10u32), 1);
+        assert_eq!(t.rank(&11u32), 2);
+        assert_eq!(t.rank(&u32::MAX), 4);
+        assert_eq!(CritBit::<u32, ()>::new().rank(&5u32), 0);
+
+        let t: CritBit<u16, ()> = (0..500u16).map(|i| (i.wrapping_mul(40503), ())).collect();
+        for q in (0..=u16::MAX).step_by(97) {
+            assert_eq!(t.rank(&q), t.keys().filter(|k| **k < q).count());
+        }
+    }
+
+    #[test]
+    fn select() {
+        let t: CritBit<u16, u16> = (0..500u16).map(|i| (i.wrapping_mul(40503), i)).collect();
+        assert_eq!(t.select(0), t.first());
+        assert_eq!(t.select(t.len() - 1), t.last());
+        assert_eq!(t.select(0).map(|(k, _)| k), t.min_key());
+        assert_eq!(t.select(t.len() - 1).map(|(k, _)| k), t.max_key());
+        assert_eq!(t.select(t.len()), None);
+
+        for (n, entry) in t.iter().enumerate() {
+            assert_eq!(t.select(n), Some(entry));
+            assert_eq!(t.rank(entry.0), n);
+        }
+        assert_eq!(CritBit::<u16, u16>::new().select(0), None);
+    }
+
+    #[test]
+    fn drain() {
+        let mut t: CritBit<u16, Box<u16>> = (0..100u16).rev().map(|k| (k, Box::new(k))).collect();
+        let drained: Vec<(u16, Box<u16>)> = t.drain().collect();
+        assert!(t.is_empty());
+        assert_eq!(drained.len(), 100);
+        assert!(drained.iter().map(|(k, _)| *k).eq(0..100u16));
+
+        t.extend((0..100u16).map(|k| (k, Box::new(k))));
+        let capacity = t.nodes.capacity();
+        let mut drain = t.drain();
+        for k in 0..50u16 {
+            assert_eq!(drain.next(), Some((k, Box::new(k))));
+        }
+        assert_eq!(drain.len(), 50);
+        drop(drain);
+        assert!(t.is_empty());
+        assert_eq!(t.len(), 0);
+        assert_eq!(t.nodes.capacity(), capacity);
+
+        let mut leaked: CritBit<u16, u16> = (0..10u16).map(|k| (k, k)).collect();
+        core::mem::forget(leaked.drain());
+        assert!(leaked.is_empty());
+
+        t.insert(7u16, Box::new(7u16));
+        assert_eq!(t.len(), 1);
+    }
+
+    #[test]
+    fn from_sorted_slice() {
+        let mut keys: Vec<u16> = (0..1000u16).map(|i| i.wrapping_mul(40503)).collect();
+        keys.sort();
+        let pairs: Vec<(u16, u16)> = keys.iter().map(|k| (*k, !*k)).collect();
+
+        let built = CritBit::from_sorted_slice(&pairs);
+        assert_well_formed(&built);
+        let inserted: CritBit<u16, u16> = pairs.iter().copied().collect();
+        assert_eq!(built, inserted);
+        assert_eq!(built.len(), 1000);
+        assert!(keys.iter().all(|k| built.get(k) == Some(&!*k)));
+
+        let dense: Vec<(u8, ())> = (0..=255u8).map(|k| (k, ())).collect();
+        let built = CritBit::from_sorted_slice(&dense);
+        assert_well_formed(&built);
+        assert_eq!(built.len(), 256);
+
+        assert!(CritBit::<u8, ()>::from_sorted_slice(&[]).is_empty());
+        assert_eq!(
+            CritBit::from_sorted_slice(&[(3u8, 4u8)]).get(&3u8),
+            Some(&4u8)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "strictly ascending")]
+    #[cfg(debug_assertions)]
+    fn from_sorted_slice_unsorted() {
+        CritBit::from_sorted_slice(&[(2u8, ()), (1u8, ())]);
+    }
+
+    #[test]
+    fn get_key_value() {
+        let t: CritBit<u32, char> = [(7u32, 'a'), (1 << 30, 'b')].into_iter().collect();
+        assert_eq!(t.get_key_value(&7u32), Some((&7u32, &'a')));
+        assert_eq!(t.get_key_value(&(1u32 << 30)), Some((&(1u32 << 30), &'b')));
+        assert_eq!(t.get_key_value(&8u32), None);
+        assert_eq!(CritBit::<u32, char>::new().get_key_value(&7u32), None);
+    }
+
+    #[test]
+    fn index() {
+        let mut t: CritBit<u8, u8> = [(1u8, 10u8), (200, 20)].into_iter().collect();
+        assert_eq!(t[&1u8], 10u8);
+        t[&200u8] += 5;
+        assert_eq!(t[&200u8], 25u8);
+    }
+
+    #[test]
+    #[should_panic(expected = "no entry found for key")]
+    fn index_missing() {
+        let t: CritBit<u8, u8> = [(1u8, 10u8)].into_iter().collect();
+        let _ = t[&2u8];
+    }
+
+    #[test]
+    fn count_range() {
+        let t: CritBit<u32, ()> = (0..100u32).map(|k| (k, ())).collect();
+        assert_eq!(t.count_range(10..20), 10);
+        assert_eq!(t.count_range(10..=20), 11);
+        assert_eq!(
+            t.count_range((Bound::Excluded(10), Bound::Included(20))),
+            10
+        );
+        assert_eq!(t.count_range(..), 100);
+        assert_eq!(t.count_range(90..), 10);
+        assert_eq!(t.count_range(..1000), 100);
+        assert_eq!(t.count_range((Bound::Included(20), Bound::Excluded(10))), 0);
+        for lo in [0u32, 5, 50, 99, 150] {
+            for hi in [0u32, 7, 50, 100, 200] {
+                assert_eq!(t.count_range(lo..hi), t.range(lo..hi).count());
+            }
+        }
+        assert_eq!(CritBit::<u32, ()>::new().count_range(..), 0);
+    }
+
+    #[test]
+    fn nearest() {
+        let t: CritBit<u32, char> = [(0u32, 'a'), (100, 'b'), (200, 'c')].into_iter().collect();
+        assert_eq!(t.nearest(&140), Some((&100, &'b')));
+        assert_eq!(t.nearest(&160), Some((&200, &'c')));
+        assert_eq!(t.nearest(&150), Some((&100, &'b')));
+        assert_eq!(t.nearest(&100), Some((&100, &'b')));
+        assert_eq!(t.nearest(&1000), Some((&200, &'c')));
+        assert_eq!(CritBit::<u32, char>::new().nearest(&5), None);
+    }
+
+    #[test]
+    fn try_insert() {
+        let mut t: CritBit<u16, &str> = CritBit::new();
+        *t.try_insert(3, "a").unwrap() = "b";
+        assert_eq!(t.get(&3), Some(&"b"));
+
+        let err = t.try_insert(3, "c").unwrap_err();
+        assert_eq!(*err.entry.key(), 3);
+        assert_eq!(*err.entry.get(), "b");
+        assert_eq!(err.value, "c");
+        assert_eq!(t.get(&3), Some(&"b"));
+        assert_eq!(t.len(), 1);
+    }
+
+    #[test]
+    fn map_values() {
+        let keys = [40u32, 7, 1 << 20, 8, 0, 300];
+        let t: CritBit<u32, u32> = keys.iter().map(|&k| (k, k * 2)).collect();
+        let mut seen = Vec::new();
+        let m = t.map_values(|k, v| {
+            seen.push(*k);
+            v.to_string()
+        });
+        assert_well_formed(&m);
+        assert_eq!(m.len(), keys.len());
+        let mut sorted = keys.to_vec();
+        sorted.sort();
+        assert_eq!(seen, sorted);
+        assert_eq!(m.keys().copied().collect::<Vec<_>>(), sorted);
+        for k in keys {
+            assert_eq!(m.get(&k), Some(&(k * 2).to_string()));
+        }
+    }
+
+    #[test]
+    fn check_invariants() {
+        let mut t: CritBit<u8, ()> = CritBit::new();
+        assert_eq!(t.check_invariants(), Ok(()));
+        for k in [9u8, 200, 3, 64, 65, 0] {
+            t.insert(k, ());
+            assert_eq!(t.check_invariants(), Ok(()));
+        }
+
+        let leaf = |k: u8| CritBitNode::Leaf(k, ());
+        let internal = |left, right, crit, size| {
+            CritBitNode::Internal(InternalCritBitNode {
+                left,
+                right,
+                crit,
+                size,
+            })
+        };
+        let broken = [
+            // A branch pointing outside the arena.
+            (vec![leaf(1), internal(0, 7, 7, 2)], 1),
+            // Children swapped.
+            (vec![leaf(200), leaf(1), internal(0, 1, 0, 2)], 2),
+            // Crit bit doesn't match where the keys first differ.
+            (vec![leaf(1), leaf(200), internal(0, 1, 3, 2)], 2),
+            // Crit bits not increasing downwards.
+            (
+                vec![
+                    leaf(1),
+                    leaf(8),
+                    internal(0, 1, 4, 2),
+                    leaf(3),
+                    internal(2, 3, 6, 3),
+                ],
+                4,
+            ),
+            // Stale size.
+            (vec![leaf(1), leaf(3), internal(0, 1, 6, 5)], 2),
+            // A node the tree never reaches.
+            (vec![leaf(1), leaf(3), internal(0, 1, 6, 2), leaf(9)], 2),
+        ];
+        for (nodes, root) in broken {
+            assert!(CritBit { nodes, root }.check_invariants().is_err());
+        }
+    }
+
+    #[test]
+    fn depth() {
+        let mut t: CritBit<u8, ()> = CritBit::new();
+        assert_eq!((t.max_depth(), t.avg_depth()), (0, 0.0));
+        t.insert(5, ());
+        assert_eq!((t.max_depth(), t.avg_depth()), (0, 0.0));
+
+        let t: CritBit<u8, ()> = [0u8, 64, 128, 192].into_iter().map(|k| (k, ())).collect();
+        assert_eq!((t.max_depth(), t.avg_depth()), (2, 2.0));
+
+        // A chain: each key splits off one bit further down.
+        let t: CritBit<u8, ()> = [0u8, 1, 2, 4].into_iter().map(|k| (k, ())).collect();
+        assert_eq!(t.max_depth(), 3);
+        assert_eq!(t.avg_depth(), (3.0 + 3.0 + 2.0 + 1.0) / 4.0);
+    }
+
+    #[test]
+    fn entry_or_default() {
+        let mut t: CritBit<u32, Vec<&str>> = CritBit::new();
+        for (k, word) in [
+            (3u32, "c"),
+            (1, "a"),
+            (3, "cc"),
+            (2, "b"),
+            (1, "aa"),
+            (3, "ccc"),
+        ] {
+            t.entry_or_default(k).push(word);
+        }
+        assert_eq!(t.len(), 3);
+        assert_eq!(t.get(&1), Some(&vec!["a", "aa"]));
+        assert_eq!(t.get(&2), Some(&vec!["b"]));
+        assert_eq!(t.get(&3), Some(&vec!["c", "cc", "ccc"]));
+    }
+
+    #[test]
+    fn ordering() {
+        let tree = |pairs: &[(u8, u8)]| pairs.iter().copied().collect::<CritBit<u8, u8>>();
+        assert!(tree(&[(1, 9), (5, 0)]) < tree(&[(2, 0)]));
+        assert!(tree(&[(1, 0)]) < tree(&[(1, 1)]));
+        assert!(tree(&[(1, 0)]) < tree(&[(1, 0), (2, 0)]));
+        assert!(tree(&[]) < tree(&[(0, 0)]));
+        assert_eq!(
+            tree(&[(3, 3)]).cmp(&tree(&[(3, 3)])),
+            std::cmp::Ordering::Equal
+        );
+
+        let mut trees = [tree(&[(7, 0)]), tree(&[(2, 0)]), tree(&[(4, 0)])];
+        trees.sort();
+        let firsts: Vec<_> = trees.iter().map(|t| *t.min_key().unwrap()).collect();
+        assert_eq!(firsts, [2, 4, 7]);
+    }
+
+    #[test]
+    fn with_capacity() {
+        let n = 1_000u32;
+        let mut t: CritBit<u32, u32> = CritBit::with_capacity(n as usize);
+        assert!(t.is_empty());
+        for k in 0..n {
+            t.insert(k.wrapping_mul(2_654_435_761), k);
+        }
+        t.reserve(10);
+        assert_well_formed(&t);
+        assert_eq!(t.len(), n as usize);
+        for k in 0..n {
+            assert_eq!(t.get(&k.wrapping_mul(2_654_435_761)), Some(&k));
+        }
+    }
+
+    #[test]
+    fn remove_keeps_arena_dense() {
+        // Removals move the arena's last nodes into the freed slots, so mix
+        // them with inserts and check every link still lands where it should.
+        let mut t: CritBit<u16, u32> = CritBit::new();
+        let mut model = std::collections::BTreeMap::new();
+        let mut state = 0x1234_5678u32;
+        for i in 0..4_000u32 {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let k = (state % 512) as u16;
+            if state.is_multiple_of(3) {
+                assert_eq!(t.remove(&k), model.remove(&k));
+            } else {
+                assert_eq!(t.insert(k, i), model.insert(k, i));
+            }
+            assert_eq!(t.check_invariants(), Ok(()));
+        }
+        assert_well_formed(&t);
+        assert!(t.iter().map(|(k, v)| (*k, *v)).eq(model.into_iter()));
+        while t.pop_min().is_some() {
+            assert_eq!(t.check_invariants(), Ok(()));
+        }
+        assert!(t.is_empty());
+    }
+
+    #[test]
+    fn get_many_mut() {
+        let mut t: CritBit<u32, u32> = (0..50u32).map(|k| (k * 7, k)).collect();
+        let [a, b, c] = t.get_many_mut([&21, &0, &343]).unwrap();
+        assert_eq!((*a, *b, *c), (3, 0, 49));
+        std::mem::swap(a, c);
+        *b += 100;
+        assert_eq!(t.get(&21), Some(&49));
+        assert_eq!(t.get(&343), Some(&3));
+        assert_eq!(t.get(&0), Some(&100));
+
+        assert!(t.get_many_mut([&7, &14, &7]).is_none());
+        assert!(t.get_many_mut([&7, &8]).is_none());
+        assert!(t.get_many_mut::<0>([]).is_some());
+        assert!(CritBit::<u32, u32>::new().get_many_mut([&1]).is_none());
+    }
+
+    #[test]
+    fn first_last_entry() {
+        let mut t: CritBit<u8, u32> = [(30u8, 3u32), (10, 1), (20, 2)].into_iter().collect();
+        let mut min = t.first_entry().unwrap();
+        assert_eq!((*min.key(), *min.get()), (10, 1));
+        *min.get_mut() += 10;
+        assert_eq!(t.get(&10), Some(&11));
+
+        let min = t.first_entry().unwrap();
+        assert_eq!(min.remove_entry(), (10, 11));
+        assert_eq!(t.first_entry().map(|e| *e.key()), Some(20));
+
+        assert_eq!(t.last_entry().unwrap().remove(), 3);
+        assert_eq!(t.len(), 1);
+        t.last_entry().unwrap().remove();
+        assert!(t.first_entry().is_none());
+        assert!(t.last_entry().is_none());
+    }
+
+    #[test]
+    fn clear_keeps_capacity() {
+        let mut t: CritBit<u32, u32> = CritBit::new();
+        for round in 0..3u32 {
+            for k in 0..1_000u32 {
+                t.insert(k.wrapping_mul(2_654_435_761), k + round);
+            }
+            let capacity = t.nodes.capacity();
+            assert_eq!(t.len(), 1_000);
+            assert_eq!(t.get(&2_654_435_761), Some(&(1 + round)));
+            assert_well_formed(&t);
+
+            t.clear();
+            assert!(t.is_empty());
+            assert_eq!(t.get(&0), None);
+            assert_eq!(t.nodes.capacity(), capacity);
+        }
+    }
+
+    #[test]
+    fn signed_keys() {
+        assert!(!bit_at(&-1i8, &0));
+        assert!(bit_at(&0i8, &0));
+        assert!(bit_at(&-1i8, &1));
+
+        let mut t: CritBit<i32, ()> = CritBit::new();
+        for k in [3, -1, 7, 0, -5i32] {
+            t.insert(k, ());
+        }
+        assert_eq!(t.keys().copied().collect::<Vec<_>>(), [-5, -1, 0, 3, 7]);
+        assert_eq!(t.check_invariants(), Ok(()));
+        assert_eq!(t.min_key(), Some(&-5));
+        assert_eq!(t.max_key(), Some(&7));
+        assert_eq!(t.rank(&0), 2);
+        assert_eq!(t.floor(&-2).map(|(k, _)| *k), Some(-5));
+        assert_eq!(t.ceiling(&-2).map(|(k, _)| *k), Some(-1));
+        assert_eq!(
+            t.range(-3..5).map(|(k, _)| *k).collect::<Vec<_>>(),
+            [-1, 0, 3]
+        );
+
+        let extremes: CritBit<i8, ()> = [i8::MAX, 0, i8::MIN, -1, 1]
+            .iter()
+            .map(|&k| (k, ()))
+            .collect();
+        assert_eq!(
+            extremes.keys().copied().collect::<Vec<_>>(),
+            [i8::MIN, -1, 0, 1, i8::MAX]
+        );
+        assert_eq!(
+            CritBit::from_sorted_slice(&[(-3i16, ()), (-2, ()), (4, ())]).check_invariants(),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn into_sorted_vec() {
+        let input = [9u16, 3, 700, 3, 0, 42, 9, 65535, 42];
+        let t: CritBit<u16, usize> = input.iter().enumerate().map(|(i, &k)| (k, i)).collect();
+        let sorted = t.into_sorted_vec();
+        assert_eq!(sorted.len(), 6);
+        assert!(sorted.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(
+            sorted,
+            [(0, 4), (3, 3), (9, 6), (42, 8), (700, 2), (65535, 7)]
+        );
+    }
+
+    #[test]
+    fn extend_from_slice() {
+        const TABLE: &[(u8, char)] = &[(1, 'a'), (5, 'e'), (3, 'c'), (5, 'E')];
+        let mut first: CritBit<u8, char> = CritBit::new();
+        let mut second: CritBit<u8, char> = [(9, 'i')].into_iter().collect();
+        first.extend_from_slice(TABLE);
+        second.extend_from_slice(TABLE);
+        assert_eq!(first.len(), 3);
+        assert_eq!(second.len(), 4);
+        for t in [&first, &second] {
+            assert_eq!(t.get(&1), Some(&'a'));
+            assert_eq!(t.get(&3), Some(&'c'));
+            assert_eq!(t.get(&5), Some(&'E'));
+        }
+        assert!(!first.contains_key(&9));
+    }
+
+    #[test]
+    fn insert_checked() {
+        fn path_depth<K: PrimInt, V>(t: &CritBit<K, V>, key: &K) -> usize {
+            let mut depth = 0;
+            let mut at = t.root;
+            while let CritBitNode::Internal(InternalCritBitNode {
+                left, right, crit, ..
+            }) = t.nodes[at as usize]
+            {
+                at = if bit_at(key, &crit) { right } else { left };
+                depth += 1;
+            }
+            depth
+        }
+
+        let mut t: CritBit<u32, u32> = CritBit::new();
+        assert_eq!(t.insert_checked(5, 0), (None, 0));
+        for i in 1..500u32 {
+            let k = i.wrapping_mul(2_654_435_761) >> (i % 20);
+            let old = t.get(&k).copied();
+            let (displaced, depth) = t.insert_checked(k, i);
+            assert_eq!(displaced, old);
+            assert_eq!(depth, path_depth(&t, &k));
+        }
+        assert_well_formed(&t);
+        assert_eq!(t.insert_checked(5, 9), (Some(0), path_depth(&t, &5)));
+    }
+
+    #[test]
+    fn iter_rev() {
+        let mut t: CritBit<i32, i32> = CritBit::new();
+        assert_eq!(t.iter().next_back(), None);
+        for i in 0..300i32 {
+            let k = i.wrapping_mul(-1_640_531_535) >> (i % 13);
+            t.insert(k, -k);
+        }
+        let keys: Vec<i32> = t.iter().rev().map(|(k, _)| *k).collect();
+        assert_eq!(keys.len(), t.len());
+        assert!(keys.windows(2).all(|w| w[0] > w[1]));
+        assert!(t.keys().rev().eq(keys.iter()));
+        assert!(t.values().rev().map(|v| -v).eq(keys.iter().copied()));
+
+        // Walking in from both ends meets in the middle without repeats.
+        let mut it = t.keys();
+        let (mut front, mut back) = (Vec::new(), Vec::new());
+        while let Some(k) = it.next() {
+            front.push(*k);
+            match it.next_back() {
+                Some(k) => back.push(*k),
+                None => break,
+            }
+        }
+        front.extend(back.into_iter().rev());
+        assert!(front.iter().rev().eq(keys.iter()));
+    }
+
+    #[test]
+    fn exact_size_iterators() {
+        let mut t: CritBit<u16, u16> = (0..200u16).map(|i| (i.wrapping_mul(40_503), i)).collect();
+        assert_eq!(t.iter().len(), t.len());
+        assert_eq!(t.keys().len(), t.len());
+        assert_eq!(t.values().len(), t.len());
+        assert_eq!(t.iter_mut().len(), t.len());
+
+        let mut it = t.iter();
+        for left in (0..t.len()).rev() {
+            if left % 2 == 0 {
+                it.next();
+            } else {
+                it.next_back();
+            }
+            assert_eq!(it.len(), left);
+        }
+        assert_eq!(it.next(), None);
+        let mut it = t.iter_mut();
+        it.nth(49);
+        assert_eq!(it.len(), 150);
+
+        let mut it = t.clone().into_iter();
+        assert_eq!(it.len(), 200);
+        it.by_ref().take(120).for_each(drop);
+        assert_eq!(it.len(), 80);
+        assert_eq!(it.count(), 80);
+        assert_eq!(CritBit::<u8, ()>::new().iter().len(), 0);
+    }
+
+    #[test]
+    fn longest_matching_prefix() {
+        let mut t: CritBit<u8, &str> = CritBit::new();
+        assert_eq!(t.longest_matching_prefix(&0), None);
+        t.insert(0b1100_0000, "a");
+        t.insert(0b1000_0000, "b");
+        assert_eq!(
+            t.longest_matching_prefix(&0b1110_0000),
+            Some((&0b1100_0000, &"a"))
+        );
+        // 0b0111_1111 is numerically nearest but doesn't even share the top bit.
+        t.insert(0b0111_1111, "c");
+        t.remove(&0b1000_0000);
+        assert_eq!(t.nearest(&0b1000_0001), Some((&0b0111_1111, &"c")));
+        assert_eq!(
+            t.longest_matching_prefix(&0b1000_0001),
+            Some((&0b1100_0000, &"a"))
+        );
+        t.insert(0b1000_0000, "b");
+        assert_eq!(
+            t.longest_matching_prefix(&0b1011_1111),
+            Some((&0b1000_0000, &"b"))
+        );
+        assert_eq!(
+            t.longest_matching_prefix(&0b1100_0000),
+            Some((&0b1100_0000, &"a"))
+        );
+
+        let keys: Vec<u32> = (1..300u32).map(|i| i.wrapping_mul(2_654_435_761)).collect();
+        let t: CritBit<u32, ()> = keys.iter().map(|&k| (k, ())).collect();
+        for q in (0..100u32).map(|i| i.wrapping_mul(40_503) << 7) {
+            let best = keys.iter().map(|k| (k ^ q).leading_zeros()).max().unwrap();
+            let (k, _) = t.longest_matching_prefix(&q).unwrap();
+            assert_eq!((k ^ q).leading_zeros(), best);
+        }
+    }
+
+    #[test]
+    fn merge_with() {
+        let mut a: CritBit<u32, u32> = (0..100u32).map(|i| (i * 3, 1)).collect();
+        let b: CritBit<u32, u32> = (0..100u32).map(|i| (i * 5, 10)).collect();
+        let mut seen = Vec::new();
+        a.merge_with(b, |k, mine, theirs| {
+            seen.push(*k);
+            *mine += theirs;
+        });
+        assert_well_formed(&a);
+        assert_eq!(seen, (0..20u32).map(|i| i * 15).collect::<Vec<_>>());
+        for k in 0..500u32 {
+            let want = match (k % 3 == 0 && k < 300, k % 5 == 0) {
+                (true, true) => Some(11),
+                (true, false) => Some(1),
+                (false, true) => Some(10),
+                (false, false) => None,
+            };
+            assert_eq!(a.get(&k).copied(), want);
+        }
+        assert_eq!(a.len(), 180);
+
+        let mut empty = CritBit::new();
+        empty.merge_with(a.clone(), |_, _, _| unreachable!());
+        assert_eq!(empty, a);
+        a.merge_with(CritBit::new(), |_, _, _| unreachable!());
+        assert_eq!(empty, a);
+    }
+
+    #[test]
+    fn hash_follows_contents() {
+        use std::collections::HashSet;
+        use std::hash::{BuildHasher, RandomState};
+
+        let hasher = RandomState::new();
+        let a: CritBit<u32, u32> = (0..100u32).map(|i| (i * 7, i)).collect();
+        let b: CritBit<u32, u32> = (0..100u32).rev().map(|i| (i * 7, i)).collect();
+        assert_eq!(a, b);
+        assert_eq!(hasher.hash_one(&a), hasher.hash_one(&b));
+
+        let mut c = b.clone();
+        c.insert(1, 1);
+        c.remove(&1);
+        assert_eq!(hasher.hash_one(&a), hasher.hash_one(&c));
+        c.insert(7, 0);
+        assert_ne!(hasher.hash_one(&a), hasher.hash_one(&c));
+
+        let set: HashSet<CritBit<u32, u32>> = [a, b, c, CritBit::new()].into_iter().collect();
+        assert_eq!(set.len(), 3);
+    }
+
+    #[test]
+    fn btreemap_round_trip() {
+        use std::collections::BTreeMap;
+
+        let map: BTreeMap<i16, i16> = (0..500i16).map(|i| (i.wrapping_mul(-12_345), i)).collect();
+        let t = CritBit::from(map.clone());
+        assert_well_formed(&t);
+        assert!(t.iter().eq(map.iter()));
+        assert_eq!(BTreeMap::from(t), map);
+
+        let empty = CritBit::from(BTreeMap::<u8, ()>::new());
+        assert!(empty.is_empty());
+        assert!(BTreeMap::from(empty).is_empty());
+    }
+
+    #[test]
+    fn coalesce() {
+        let mut t: CritBit<u8, char> = (0..10u8).map(|k| (k, 'a')).collect();
+        t.coalesce(|a, b| a == b);
+        assert_eq!(t.into_sorted_vec(), vec![(0, 'a')]);
+
+        let mut t: CritBit<u8, u8> = [(1, 1), (3, 1), (4, 2), (9, 2), (10, 1), (20, 3)]
+            .into_iter()
+            .collect();
+        t.coalesce(|a, b| a == b);
+        assert_well_formed(&t);
+        assert_eq!(t.into_sorted_vec(), vec![(1, 1), (4, 2), (10, 1), (20, 3)]);
+
+        // Runs are measured from the entry that's kept, so drifting values
+        // start a new run once they stray too far from it.
+        let mut t: CritBit<u8, u8> = (0..10u8).map(|k| (k, k)).collect();
+        t.coalesce(|a, b| b - a < 3);
+        assert_eq!(t.keys().copied().collect::<Vec<_>>(), vec![0, 3, 6, 9]);
+
+        let mut t: CritBit<u8, u8> = CritBit::new();
+        t.coalesce(|_, _| true);
+        assert!(t.is_empty());
+    }
+
+    #[test]
+    fn root_crit_bit() {
+        let mut t: CritBit<u8, ()> = CritBit::new();
+        assert_eq!(t.root_crit_bit(), None);
+        t.insert(0b0000_0001, ());
+        assert_eq!(t.root_crit_bit(), None);
+        t.insert(0b0000_0100, ());
+        assert_eq!(t.root_crit_bit(), Some(5));
+        t.insert(0b1000_0000, ());
+        assert_eq!(t.root_crit_bit(), Some(0));
+        t.remove(&0b1000_0000);
+        assert_eq!(t.root_crit_bit(), Some(5));
+
+        let t: CritBit<i8, ()> = [(-1, ()), (1, ())].into_iter().collect();
+        assert_eq!(t.root_crit_bit(), Some(0));
+    }
+
+    #[test]
+    fn to_dot() {
+        let t: CritBit<u8, ()> = CritBit::new();
+        assert_eq!(t.to_dot(), "digraph critbit {\n}\n");
+
+        let t: CritBit<u8, ()> = [(1, ()), (4, ()), (200, ())].into_iter().collect();
+        let dot = t.to_dot();
+        assert!(dot.starts_with("digraph critbit {\n") && dot.ends_with("}\n"));
+        let nodes = dot
+            .lines()
+            .filter(|l| l.contains(" [") && !l.contains("->"));
+        assert_eq!(nodes.count(), 5);
+        assert_eq!(dot.lines().filter(|l| l.contains("->")).count(), 4);
+        assert_eq!(dot.matches("shape=box").count(), 3);
+        assert!(dot.contains("label=\"200\""));
+        assert!(dot.contains("label=\"crit 0\""));
+        assert!(dot.contains("label=\"crit 5\""));
+    }
+
+    #[test]
+    fn replace() {
+        let mut t: CritBit<i64, &str> = CritBit::new();
+        assert_eq!(t.replace(-3, "a"), None);
+        assert_eq!(t.replace(7, "b"), None);
+        assert_eq!(t.replace(-3, "c"), Some((-3, "a")));
+        assert_eq!(t.replace(-3, "d"), Some((-3, "c")));
+        assert_eq!(t.get(&-3), Some(&"d"));
+        assert_eq!(t.get(&7), Some(&"b"));
+        assert_eq!(t.len(), 2);
+    }
+
+    #[test]
+    fn peek_mut() {
+        let mut t: CritBit<i32, u32> = CritBit::new();
+        assert_eq!(t.peek_min_mut(), None);
+        assert_eq!(t.peek_max_mut(), None);
+        t.extend([(5, 0), (-20, 0), (13, 0), (0, 0)]);
+        for _ in 0..3 {
+            let (k, v) = t.peek_min_mut().unwrap();
+            assert_eq!(*k, -20);
+            *v += 1;
+        }
+        *t.peek_max_mut().unwrap().1 = 99;
+        assert_eq!(t.get(&-20), Some(&3));
+        assert_eq!(t.get(&13), Some(&99));
+        assert_eq!(t.get(&0), Some(&0));
+        assert_eq!(t.len(), 4);
+    }
+
+    #[test]
+    fn partition_point() {
+        let t: CritBit<u32, u32> = (0..100u32).map(|k| (k, k * 2)).collect();
+        assert_eq!(t.partition_point(|k| *k < 42), Some((&42, &84)));
+        assert_eq!(t.partition_point(|_| false), Some((&0, &0)));
+        assert_eq!(t.partition_point(|_| true), None);
+        assert_eq!(CritBit::<u32, u32>::new().partition_point(|_| true), None);
+
+        let keys: Vec<i16> = (0..400i16).map(|i| i.wrapping_mul(-7_919)).collect();
+        let t: CritBit<i16, ()> = keys.iter().map(|&k| (k, ())).collect();
+        for split in (-70..70i16).map(|i| i * 450) {
+            let want = keys.iter().filter(|&&k| k >= split).min();
+            assert_eq!(t.partition_point(|k| *k < split).map(|(k, _)| k), want);
+        }
+    }
+
+    #[test]
+    fn split_at_rank() {
+        let mut t: CritBit<u8, u8> = (0..10u8).map(|k| (k, k)).collect();
+        let low = t.split_at_rank(4);
+        assert_well_formed(&t);
+        assert_well_formed(&low);
+        assert!(t.keys().copied().eq(4..10));
+        assert!(low.keys().copied().eq(0..4));
+
+        let none = t.split_at_rank(0);
+        assert!(none.is_empty());
+        assert_eq!(t.len(), 6);
+        let all = t.split_at_rank(6);
+        assert!(t.is_empty());
+        assert!(all.keys().copied().eq(4..10));
+        let mut t = all;
+        assert_eq!(t.split_at_rank(100).len(), 6);
+        assert!(t.is_empty());
+
+        // Big enough for either side to be split off on its own.
+        let mut t: CritBit<u16, u16> = (0..1000u16).map(|k| (k.wrapping_mul(40_503), k)).collect();
+        let keys: Vec<u16> = t.keys().copied().collect();
+        let low = t.split_at_rank(3);
+        assert_well_formed(&t);
+        assert_well_formed(&low);
+        assert!(low.keys().eq(&keys[..3]));
+        let low = t.split_at_rank(995);
+        assert_well_formed(&t);
+        assert_well_formed(&low);
+        assert!(low.keys().eq(&keys[3..998]));
+        assert!(t.keys().eq(&keys[998..]));
+
+        // Two big halves, so the arena is closed up behind the cut in one pass.
+        let mut t: CritBit<u16, u16> = (0..1000u16).map(|k| (k.wrapping_mul(40_503), k)).collect();
+        let low = t.split_at_rank(400);
+        assert_well_formed(&t);
+        assert_well_formed(&low);
+        assert!(low.keys().chain(t.keys()).eq(&keys));
+        assert_eq!((low.len(), t.len()), (400, 600));
+    }
+
+    #[test]
+    fn borrowed_lookups() {
+        use std::borrow::Borrow;
+
+        fn probe<K: PrimInt + Borrow<Q>, Q: PrimInt>(t: &mut CritBit<K, u8>, q: &Q) -> bool {
+            let got = t.get(q).copied();
+            t.contains_key(q) && got == t.get_mut(q).map(|v| *v)
+        }
+
+        let mut t: CritBit<u64, u8> = (0..50u64).map(|k| (k << 40, k as u8)).collect();
+        let key: u64 = 7 << 40;
+        let borrowed: &u64 = key.borrow();
+        assert!(probe(&mut t, borrowed));
+        assert_eq!(t.get::<u64>(borrowed), Some(&7));
+        assert_eq!(t.get_key_value(borrowed), Some((&key, &7)));
+        assert_eq!(t.remove(borrowed), Some(7));
+        assert!(!probe(&mut t, borrowed));
+        assert_eq!(t.remove(borrowed), None);
+        assert_eq!(t.len(), 49);
+    }
+
+    #[test]
+    fn cursor_mut() {
+        let mut t: CritBit<u32, u32> = (0..20u32).map(|k| (k * 10, 0)).collect();
+        let mut c = t.lower_bound_mut(&45);
+        assert_eq!(c.current().map(|(k, _)| *k), Some(50));
+        let mut seen = Vec::new();
+        while let Some((k, v)) = c.current() {
+            seen.push(*k);
+            *v = 1;
+            c.move_next();
+        }
+        assert_eq!(seen, (5..20u32).map(|k| k * 10).collect::<Vec<_>>());
+        // Off the end, it wraps round in either direction.
+        c.move_prev();
+        assert_eq!(c.current().map(|(k, _)| *k), Some(190));
+        c.move_next();
+        assert!(c.current().is_none());
+        c.move_next();
+        assert_eq!(c.current().map(|(k, _)| *k), Some(0));
+        c.move_prev();
+        assert!(c.current().is_none());
+
+        let mut c = t.lower_bound_mut(&50);
+        c.move_prev();
+        let (k, v) = c.current().unwrap();
+        assert_eq!((*k, *v), (40, 0));
+        *v = 2;
+        assert!(t.lower_bound_mut(&191).current().is_none());
+        assert_eq!(t.lower_bound_mut(&0).current().map(|(k, _)| *k), Some(0));
+        assert_eq!(t.get(&40), Some(&2));
+        assert_eq!(t.values().filter(|&&v| v == 1).count(), 15);
+
+        let mut empty: CritBit<i8, ()> = CritBit::new();
+        let mut c = empty.lower_bound_mut(&0);
+        c.move_next();
+        c.move_prev();
+        assert!(c.current().is_none());
+    }
+
+    #[test]
+    fn remove_range() {
+        let mut t: CritBit<u16, u16> = (0..50u16).map(|k| (k, k)).collect();
+        assert_eq!(t.remove_range(20..30), 10);
+        assert_well_formed(&t);
+        assert_eq!(t.len(), 40);
+        assert_eq!(t.range(20..30).count(), 0);
+        assert!(t.keys().copied().eq((0..20).chain(30..50)));
+
+        assert_eq!(t.remove_range(20..30), 0);
+        assert_eq!(
+            t.remove_range((Bound::Excluded(30), Bound::Included(32))),
+            2
+        );
+        assert_eq!(t.remove_range(..=1), 2);
+        assert_eq!(t.remove_range(45..), 5);
+        assert_well_formed(&t);
+        assert!(t.keys().copied().eq((2..20).chain([30]).chain(33..45)));
+        assert_eq!(t.remove_range(..), 31);
+        assert!(t.is_empty());
+        assert_eq!(t.remove_range(..), 0);
+
+        let mut t: CritBit<u16, u16> = (0..1000u16).map(|k| (k.wrapping_mul(40_503), k)).collect();
+        let keys: Vec<u16> = t.keys().copied().collect();
+        assert_eq!(t.remove_range(keys[500]..keys[505]), 5);
+        assert_well_formed(&t);
+        assert_eq!(t.len(), 995);
+        assert!(t.keys().eq(keys[..500].iter().chain(&keys[505..])));
+    }
+
+    #[test]
+    fn remove_range_matches_btreemap() {
+        use std::collections::BTreeMap;
+
+        let pairs = (0..2000u16).map(|i| (i.wrapping_mul(40_503), i));
+        // Spans from a handful of keys, taken out one at a time, up to most
+        // of the tree, where the arena is closed up in one pass.
+        for (a, b) in [(0, 3), (700, 701), (100, 160), (1000, 30_000), (9, 64_000)] {
+            let mut t: CritBit<u16, u16> = pairs.clone().collect();
+            let mut m: BTreeMap<u16, u16> = pairs.clone().collect();
+            let expect = m.range(a..b).count();
+            m.retain(|k, _| !(a..b).contains(k));
+            assert_eq!(t.remove_range(a..b), expect);
+            assert_well_formed(&t);
+            assert!(t.iter().eq(m.iter()));
+        }
+    }
+
+    #[test]
+    fn get_or_insert() {
+        use std::cell::Cell;
+        use std::collections::BTreeMap;
+
+        // Counts how many values have been dropped, to show a present key's
+        // spare default is thrown away rather than stored.
+        struct Counted<'a>(u32, &'a Cell<usize>);
+        impl Drop for Counted<'_> {
+            fn drop(&mut self) {
+                self.1.set(self.1.get() + 1);
+            }
+        }
+
+        let drops = Cell::new(0);
+        let mut t: CritBit<u16, Counted> = CritBit::new();
+        let mut model = BTreeMap::new();
+        for i in 0..2_000u32 {
+            let k = (i.wrapping_mul(2_654_435_761) >> 22) as u16;
+            let before = drops.get();
+            t.get_or_insert(k, Counted(0, &drops)).0 += 1;
+            let present = model.contains_key(&k);
+            *model.entry(k).or_insert(0) += 1;
+            assert_eq!(drops.get() - before, usize::from(present));
+        }
+        assert_well_formed(&t);
+        assert!(t.iter().map(|(k, v)| (*k, v.0)).eq(model.into_iter()));
+        drops.set(0);
+        let n = t.len();
+        drop(t);
+        assert_eq!(drops.get(), n);
+    }
+
+    #[test]
+    fn shrink_to_fit() {
+        let mut t: CritBit<u32, u32> = (0..1_000u32).map(|k| (k.wrapping_mul(40_503), k)).collect();
+        let peak = t.nodes.capacity();
+        for k in 0..900u32 {
+            assert_eq!(t.remove(&k.wrapping_mul(40_503)), Some(k));
+        }
+        assert_eq!(t.nodes.capacity(), peak);
+        t.shrink_to_fit();
+        assert!(t.nodes.capacity() < peak);
+        assert_well_formed(&t);
+        let mut values: Vec<u32> = t.values().copied().collect();
+        values.sort_unstable();
+        assert!(values.into_iter().eq(900..1_000));
+
+        t.clear();
+        t.shrink_to_fit();
+        assert_eq!(t.nodes.capacity(), 0);
+    }
+
+    #[test]
+    fn key_bits() {
+        assert_eq!(CritBit::<u8, ()>::key_bits(), 8);
+        assert_eq!(CritBit::<i16, ()>::key_bits(), 16);
+        assert_eq!(CritBit::<u64, ()>::key_bits(), 64);
+        assert_eq!(CritBit::<i128, ()>::key_bits(), 128);
+    }
+
+    #[test]
+    fn insert_if_absent() {
+        let mut t: CritBit<u32, &str> = CritBit::new();
+        assert!(t.insert_if_absent(3, "a"));
+        assert!(t.insert_if_absent(9, "b"));
+        assert!(!t.insert_if_absent(3, "c"));
+        assert_eq!(t.get(&3), Some(&"a"));
+        assert_eq!(t.get(&9), Some(&"b"));
+        assert_eq!(t.len(), 2);
+
+        for k in 0..300u32 {
+            assert_eq!(t.insert_if_absent(k * 3, "d"), k != 1 && k != 3);
+        }
+        assert_well_formed(&t);
+        assert_eq!(t.len(), 300);
+        assert_eq!(t.get(&9), Some(&"b"));
+    }
+
+    #[test]
+    fn group_by_prefix() {
+        let t: CritBit<u8, u32> = (0..=255u8)
+            .filter(|k| k % 7 == 0)
+            .map(|k| (k, u32::from(k)))
+            .collect();
+        let groups = t.group_by_prefix(4, |sum, _, v| sum + v);
+        let mut want: Vec<(u8, u32)> = Vec::new();
+        for (k, v) in &t {
+            match want.last_mut() {
+                Some((prefix, sum)) if *prefix == k & 0xf0 => *sum += v,
+                _ => want.push((k & 0xf0, *v)),
+            }
+        }
+        assert_eq!(groups.len(), 16);
+        assert_eq!(groups, want);
+        assert_eq!(groups[0], (0x00, 7 + 14));
+
+        let counts = t.group_by_prefix(0, |n: usize, _, _| n + 1);
+        assert_eq!(counts, vec![(0, t.len())]);
+        let singles = t.group_by_prefix(64, |n: usize, _, _| n + 1);
+        assert!(singles.iter().map(|g| g.0).eq(t.keys().copied()));
+        assert!(singles.iter().all(|g| g.1 == 1));
+
+        let t: CritBit<i8, ()> = [(-128, ()), (-1, ()), (0, ()), (127, ())]
+            .into_iter()
+            .collect();
+        let signs = t.group_by_prefix(1, |n: usize, _, _| n + 1);
+        assert_eq!(signs, vec![(-128, 2), (0, 2)]);
+        assert!(
+            CritBit::<u8, ()>::new()
+                .group_by_prefix(4, |(), _, _| ())
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn node_count() {
+        let mut t: CritBit<u32, ()> = CritBit::new();
+        assert_eq!(t.node_count(), 0);
+        for n in [1u32, 2, 3, 10, 257] {
+            t = (0..n)
+                .map(|k| (k.wrapping_mul(2_654_435_761), ()))
+                .collect();
+            assert_eq!(t.node_count(), 2 * t.len() - 1);
+        }
+        for k in 0..200u32 {
+            t.remove(&k.wrapping_mul(2_654_435_761));
+            assert_eq!(t.node_count(), 2 * t.len() - 1);
+        }
+        t.clear();
+        assert_eq!(t.node_count(), 0);
+    }
+
+    #[test]
+    fn from_iter_with() {
+        let pairs = [(3u8, 1u8), (1, 5), (3, 9), (2, 2), (3, 4), (1, 3)];
+        let t = CritBit::from_iter_with(pairs, |_, a, b| a.max(b));
+        assert_well_formed(&t);
+        assert_eq!(t.into_sorted_vec(), vec![(1, 5), (2, 2), (3, 9)]);
+
+        let mut order = Vec::new();
+        let t = CritBit::from_iter_with(pairs, |k, a, b| {
+            order.push((*k, a, b));
+            a
+        });
+        assert_eq!(t.into_sorted_vec(), vec![(1, 5), (2, 2), (3, 1)]);
+        assert_eq!(order, vec![(1, 5, 3), (3, 1, 9), (3, 1, 4)]);
+
+        let keys = (0..1_000u32).map(|i| (i.wrapping_mul(40_503) % 97, 1u32));
+        let t = CritBit::from_iter_with(keys, |_, a, b| a + b);
+        assert_well_formed(&t);
+        assert_eq!(t.len(), 97);
+        assert_eq!(t.values().sum::<u32>(), 1_000);
+    }
+
+    #[test]
+    fn retain_range() {
+        let mut t: CritBit<u16, u16> = (0..50u16).map(|k| (k, k)).collect();
+        let mut shown = Vec::new();
+        t.retain_range(10..30, |k, v| {
+            shown.push(*k);
+            *v += 100;
+            k % 2 == 0
+        });
+        assert_well_formed(&t);
+        assert!(shown.into_iter().eq(10..30));
+        assert_eq!(t.len(), 40);
+        assert!(
+            t.range(..10)
+                .map(|(k, v)| (*k, *v))
+                .eq((0..10).map(|k| (k, k)))
+        );
+        assert!(
+            t.range(30..)
+                .map(|(k, v)| (*k, *v))
+                .eq((30..50).map(|k| (k, k)))
+        );
+        assert!(
+            t.range(10..30)
+                .map(|(k, v)| (*k, *v))
+                .eq((10..30).step_by(2).map(|k| (k, k + 100)))
+        );
+
+        t.retain_range(100.., |_, _| unreachable!());
+        t.retain_range(..=5, |_, _| false);
+        assert_eq!(t.first(), Some((&6, &6)));
+        assert_eq!(t.len(), 34);
+
+        let mut shown = Vec::new();
+        t.retain_range((Bound::Excluded(6), Bound::Excluded(12)), |k, _| {
+            shown.push(*k);
+            *k != 8
+        });
+        assert_eq!(shown, [7, 8, 9, 10]);
+        assert!(t.keys().copied().take(5).eq([6, 7, 9, 10, 12]));
+        t.retain_range(..3, |_, _| unreachable!());
+        assert_well_formed(&t);
+        assert_eq!(t.len(), 33);
+    }
+
+    #[test]
+    fn swap() {
+        let mut t: CritBit<i32, String> = CritBit::new();
+        t.insert(-4, "minus four".to_string());
+        t.insert(10, "ten".to_string());
+        t.insert(3, "three".to_string());
+        assert!(t.swap(&-4, &10));
+        assert_eq!(t[&-4], "ten");
+        assert_eq!(t[&10], "minus four");
+        assert_eq!(t[&3], "three");
+
+        assert!(!t.swap(&3, &7));
+        assert!(!t.swap(&7, &3));
+        assert_eq!(t[&3], "three");
+        assert!(t.swap(&3, &3));
+        assert!(!t.swap(&7, &7));
+        assert_eq!(t.len(), 3);
+    }
+
+    #[test]
+    fn iter_from() {
+        let t: CritBit<u32, u32> = (0..100u32).map(|k| (k, k)).collect();
+        let it = t.iter_from(&50);
+        assert_eq!(it.len(), 50);
+        assert!(it.map(|(k, _)| *k).eq(50..100));
+        assert!(t.iter_from(&50).rev().map(|(k, _)| *k).eq((50..100).rev()));
+        assert_eq!(t.iter_from(&0).count(), 100);
+        assert_eq!(t.iter_from(&100).next(), None);
+
+        let t: CritBit<i16, ()> = (-300..300i16).step_by(7).map(|k| (k, ())).collect();
+        for from in -310..310i16 {
+            let want = t.keys().filter(|&&k| k >= from);
+            assert!(t.iter_from(&from).map(|(k, _)| k).eq(want));
+        }
+    }
+
+    #[test]
+    fn key_set_operations() {
+        let a: CritBit<u16, u16> = (0..2_000u16).step_by(3).map(|k| (k, k)).collect();
+        let b: CritBit<u16, u16> = (1_000..3_000u16).step_by(5).map(|k| (k, 0)).collect();
+        let in_a = |k: &u16| *k < 2_000 && k.is_multiple_of(3);
+        let in_b = |k: &u16| (1_000..3_000).contains(k) && k.is_multiple_of(5);
+
+        assert!(
+            a.union_keys(&b)
+                .copied()
+                .eq((0..3_000).filter(|k| in_a(k) || in_b(k)))
+        );
+        assert!(
+            a.intersection_keys(&b)
+                .copied()
+                .eq((0..3_000).filter(|k| in_a(k) && in_b(k)))
+        );
+        assert!(
+            a.difference_keys(&b)
+                .copied()
+                .eq((0..3_000).filter(|k| in_a(k) && !in_b(k)))
+        );
+        assert!(
+            b.difference_keys(&a)
+                .copied()
+                .eq((0..3_000).filter(|k| !in_a(k) && in_b(k)))
+        );
+        assert_eq!(a.intersection_keys(&b).count(), 67);
+        assert!(a.union_keys(&a).eq(a.keys()));
+        assert_eq!(a.difference_keys(&a).next(), None);
+    }
+
+    #[test]
+    fn or_insert_with_key() {
+        let mut t: CritBit<u32, u32> = CritBit::new();
+        for k in [3u32, 8, 3, 21] {
+            *t.entry(k).or_insert_with_key(|k| k * 2) += 1;
+        }
+        assert_eq!(t.into_sorted_vec(), vec![(3, 8), (8, 17), (21, 43)]);
+    }
+
+    #[test]
+    fn eq_btreemap() {
+        use std::collections::BTreeMap;
+
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let pairs: Vec<(i32, u8)> = (0..2_000)
+            .map(|_| (next() as i32 >> 20, next() as u8))
+            .collect();
+        let mut t: CritBit<i32, u8> = pairs.iter().copied().collect();
+        let mut model: BTreeMap<i32, u8> = pairs.iter().copied().collect();
+        assert!(t.eq_btreemap(&model));
+
+        let (&k, &v) = model.iter().nth(100).unwrap();
+        model.insert(k, v.wrapping_add(1));
+        assert!(!t.eq_btreemap(&model));
+        t.insert(k, v.wrapping_add(1));
+        assert!(t.eq_btreemap(&model));
+        t.remove(&k);
+        assert!(!t.eq_btreemap(&model));
+        assert!(CritBit::<i32, u8>::new().eq_btreemap(&BTreeMap::new()));
+    }
+
+    #[test]
+    fn try_insert_bounded() {
+        // Each power of two branches one bit further down than the last, so
+        // the tree is a spine and every insert lands a level deeper.
+        let mut t: CritBit<u32, u32> = CritBit::new();
+        assert_eq!(t.try_insert_bounded(0, 0, 0), Ok(None));
+        for shift in (27..32).rev() {
+            assert_eq!(t.try_insert_bounded(1 << shift, shift, 5), Ok(None));
+        }
+        assert_eq!(t.max_depth(), 5);
+        let before = t.clone();
+        assert_eq!(
+            t.try_insert_bounded(1 << 26, 26, 5),
+            Err(DepthExceeded {
+                depth: 6,
+                max_depth: 5
+            })
+        );
+        assert!(t == before);
+        assert_eq!(t.node_count(), before.node_count());
+        assert_well_formed(&t);
+
+        // Replacing a value never deepens anything.
+        assert_eq!(t.try_insert_bounded(0, 9, 0), Ok(Some(0)));
+        // A key branching off near the root is shallow enough.
+        assert_eq!(t.try_insert_bounded(u32::MAX, 1, 2), Ok(None));
+        assert_eq!(t.insert_checked(1 << 26, 26), (None, 6));
+        assert_eq!(
+            DepthExceeded {
+                depth: 3,
+                max_depth: 2
+            }
+            .to_string(),
+            "inserting would put a leaf at depth 3, past the limit of 2"
+        );
+    }
+
+    #[test]
+    fn sum_values_in_range() {
+        let t: CritBit<u32, u32> = (0..100u32).map(|k| (k, k)).collect();
+        assert_eq!(t.sum_values_in_range::<_, u64>(10..20), 145);
+        assert_eq!(t.sum_values_in_range::<_, u64>(10..=20), 165);
+        assert_eq!(t.sum_values_in_range::<_, u64>(..), 4_950);
+        assert_eq!(t.sum_values_in_range::<_, u64>(100..), 0);
+
+        let t: CritBit<i8, u8> = (-100..100i8).map(|k| (k, 200)).collect();
+        assert_eq!(t.sum_values_in_range::<_, u32>(-50..50), 20_000);
+    }
+
+    #[test]
+    fn rebuild() {
+        let mut t: CritBit<u64, u64> = CritBit::new();
+        for i in 0..3_000u64 {
+            let k = i.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 40;
+            if i % 3 == 0 {
+                t.remove(&(k ^ 1));
+            }
+            t.insert(k, i);
+        }
+        let before: Vec<(u64, u64)> = t.iter().map(|(k, v)| (*k, *v)).collect();
+        let dot = t.to_dot().lines().count();
+        t.rebuild();
+        assert_eq!(t.check_invariants(), Ok(()));
+        assert_well_formed(&t);
+        assert!(t.iter().map(|(k, v)| (*k, *v)).eq(before.iter().copied()));
+        assert_eq!(t.to_dot().lines().count(), dot);
+        assert_eq!(t.nodes.capacity(), t.node_count());
+        // Leaves now sit in the arena in key order.
+        let leaves = t.nodes.iter().filter_map(|node| match node {
+            CritBitNode::Leaf(k, _) => Some(*k),
+            CritBitNode::Internal(..) => None,
+        });
+        assert!(leaves.eq(before.iter().map(|(k, _)| *k)));
+
+        let mut empty: CritBit<u8, ()> = CritBit::new();
+        empty.rebuild();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn get_window() {
+        let t: CritBit<u8, u8> = (0..10u8).map(|k| (k, k * 10)).collect();
+        assert_eq!(
+            t.get_window::<3>(&5),
+            [Some((&5, &50)), Some((&6, &60)), Some((&7, &70))]
+        );
+        assert_eq!(
+            t.get_window::<3>(&8),
+            [Some((&8, &80)), Some((&9, &90)), None]
+        );
+        assert_eq!(t.get_window::<2>(&10), [None, None]);
+        assert_eq!(t.get_window::<0>(&0), []);
+
+        let t: CritBit<i16, ()> = (-500..500i16).step_by(9).map(|k| (k, ())).collect();
+        for start in -510..510i16 {
+            let window = t.get_window::<4>(&start);
+            let want = t.iter_from(&start).map(Some).chain([None; 4]);
+            assert!(window.into_iter().eq(want.take(4)));
+        }
+    }
+
+    #[test]
+    fn singleton() {
+        let mut t = CritBit::singleton(-7i64, "seven");
+        assert_well_formed(&t);
+        assert_eq!(t.len(), 1);
+        assert_eq!(t.get(&-7), Some(&"seven"));
+        assert_eq!(t.get(&7), None);
+        t.insert(7, "plus seven");
+        assert_well_formed(&t);
+        assert_eq!(t.len(), 2);
+    }
+
+    #[test]
+    fn into_keys_and_values() {
+        let t: CritBit<i32, String> = (-50..50i32).rev().map(|k| (k * 3, k.to_string())).collect();
+        let keys = t.clone().into_keys();
+        assert_eq!(keys.len(), 100);
+        assert!(keys.eq((-50..50).map(|k| k * 3)));
+        let mut values = t.into_values();
+        assert_eq!(values.len(), 100);
+        assert_eq!(values.next().as_deref(), Some("-50"));
+        assert_eq!(values.len(), 99);
+        assert!(values.eq((-49..50).map(|k| k.to_string())));
+    }
+
+    #[test]
+    fn contains_prefix() {
+        let t: CritBit<u8, ()> = [0x12, 0x17, 0x5a, 0xf0]
+            .into_iter()
+            .map(|k| (k, ()))
+            .collect();
+        assert!(t.contains_prefix(0x10, 4));
+        assert!(t.contains_prefix(0x1f, 4));
+        assert!(t.contains_prefix(0x50, 4));
+        assert!(t.contains_prefix(0xff, 4));
+        assert!(!t.contains_prefix(0x20, 4));
+        assert!(!t.contains_prefix(0x60, 4));
+        assert!(!t.contains_prefix(0x00, 4));
+        assert!(t.contains_prefix(0x00, 0));
+        assert!(t.contains_prefix(0x17, 8));
+        assert!(!t.contains_prefix(0x16, 8));
+        assert!(!CritBit::<u8, ()>::new().contains_prefix(0, 0));
+    }
+
+    #[test]
+    fn retain_keys() {
+        let mut t: CritBit<u8, u8> = (0..30u8).map(|k| (k, 30 - k)).collect();
+        t.retain_keys(|k| k.is_multiple_of(3));
+        assert_well_formed(&t);
+        assert_eq!(t.len(), 10);
+        assert!(
+            t.iter()
+                .map(|(k, v)| (*k, *v))
+                .eq((0..30).step_by(3).map(|k| (k, 30 - k)))
+        );
+        t.retain_keys(|_| false);
+        assert!(t.is_empty());
+        assert_eq!(t.node_count(), 0);
+    }
+
+    #[test]
+    fn bulk_remove() {
+        let mut t: CritBit<u32, u32> = (0..100u32).map(|k| (k * 2, k)).collect();
+        assert_eq!(t.bulk_remove(vec![10, 11, 50, 199, 10, 0, 198, 1_000]), 4);
+        assert_well_formed(&t);
+        assert_eq!(t.len(), 96);
+        for k in [0, 10, 50, 198] {
+            assert!(!t.contains_key(&k));
+        }
+        assert!(
+            t.keys()
+                .copied()
+                .eq((1..99).map(|k| k * 2).filter(|&k| k != 10 && k != 50))
+        );
+        assert_eq!(t.bulk_remove(Vec::new()), 0);
+        assert_eq!(t.bulk_remove(0..1_000), 96);
+        assert!(t.is_empty());
+    }
+
+    #[test]
+    fn first_missing_in() {
+        let t: CritBit<u8, ()> = [0, 1, 2, 4].into_iter().map(|k| (k, ())).collect();
+        assert_eq!(t.first_missing_in(0, 10), Some(3));
+        assert_eq!(t.first_missing_in(0, 3), None);
+        assert_eq!(t.first_missing_in(3, 10), Some(3));
+        assert_eq!(t.first_missing_in(4, 10), Some(5));
+        assert_eq!(t.first_missing_in(4, 5), None);
+        assert_eq!(t.first_missing_in(9, 9), None);
+
+        let full: CritBit<u8, ()> = (0..=255u8).map(|k| (k, ())).collect();
+        assert_eq!(full.first_missing_in(0, 255), None);
+        let mut t: CritBit<i8, ()> = (-128..=127i8).map(|k| (k, ())).collect();
+        assert_eq!(t.first_missing_in(-128, 127), None);
+        t.remove(&100);
+        assert_eq!(t.first_missing_in(-128, 127), Some(100));
+        assert_eq!(t.first_missing_in(101, 127), None);
+
+        let t: CritBit<i32, ()> = (-40..40i32)
+            .filter(|k| k % 11 != 0)
+            .map(|k| (k, ()))
+            .collect();
+        for start in -45..45i32 {
+            for end in start..45 {
+                let want = (start..end).find(|k| !t.contains_key(k));
+                assert_eq!(t.first_missing_in(start, end), want);
+            }
+        }
+    }
+
+    #[test]
+    fn for_each() {
+        let t: CritBit<i32, i32> = (0..500i32)
+            .map(|i| (i.wrapping_mul(-1_640_531_535), i))
+            .collect();
+        let mut seen = Vec::new();
+        t.for_each(|k, v| seen.push((*k, *v)));
+        assert!(seen.iter().map(|(k, v)| (k, v)).eq(t.iter()));
+        assert_eq!(seen.len(), 500);
+        CritBit::<u8, ()>::new().for_each(|_, _| unreachable!());
+    }
+
+    #[test]
+    fn try_for_each() {
+        let t: CritBit<u16, u16> = (0..100u16).map(|k| (k, k)).collect();
+        let mut seen = Vec::new();
+        let result = t.try_for_each(|k, v| {
+            if *k == 42 {
+                return Err(*v * 2);
+            }
+            seen.push(*k);
+            Ok(())
+        });
+        assert_eq!(result, Err(84));
+        assert!(seen.into_iter().eq(0..42));
+
+        let mut count = 0;
+        let result: Result<(), ()> = t.try_for_each(|_, _| {
+            count += 1;
+            Ok(())
+        });
+        assert_eq!(result, Ok(()));
+        assert_eq!(count, 100);
+    }
+
+    #[test]
+    fn position_of() {
+        let t: CritBit<u32, ()> = [10, 20, 30].into_iter().map(|k| (k, ())).collect();
+        assert_eq!(t.position_of(&10), Some(0));
+        assert_eq!(t.position_of(&20), Some(1));
+        assert_eq!(t.position_of(&30), Some(2));
+        assert_eq!(t.position_of(&25), None);
+        assert_eq!(t.position_of(&0), None);
+        assert_eq!(CritBit::<u32, ()>::new().position_of(&0), None);
+
+        let t: CritBit<i16, ()> = (-300..300i16).step_by(7).map(|k| (k, ())).collect();
+        for k in -310..310i16 {
+            let want = t.keys().position(|&x| x == k);
+            assert_eq!(t.position_of(&k), want);
+            if want.is_some() {
+                assert_eq!(t.position_of(&k), Some(t.rank(&k)));
+            }
+        }
+    }
+
+    #[test]
+    fn common_prefix_len_of_keys() {
+        assert_eq!(common_prefix_len(&0x5au8, &0x5a), 8);
+        assert_eq!(common_prefix_len(&7u64, &7), 64);
+        assert_eq!(common_prefix_len(&0x80u8, &0x00), 0);
+        assert_eq!(common_prefix_len(&-1i32, &1), 0);
+        assert_eq!(common_prefix_len(&0x1200u16, &0x1280), 8);
+
+        let t: CritBit<u8, ()> = [(0x10, ()), (0x13, ())].into_iter().collect();
+        assert_eq!(t.root_crit_bit(), Some(common_prefix_len(&0x10u8, &0x13)));
+    }
+
+    #[test]
+    fn split_by_bit() {
+        let t: CritBit<u8, u8> = (0..=255u8).map(|k| (k, k)).collect();
+        let (low, high) = t.clone().split_by_bit(0);
+        assert_well_formed(&low);
+        assert_well_formed(&high);
+        assert!(low.keys().copied().eq(0..128));
+        assert!(high.keys().copied().eq(128..=255));
+
+        let (zeros, ones) = t.clone().split_by_bit(7);
+        assert!(zeros.keys().all(|k| k % 2 == 0) && zeros.len() == 128);
+        assert!(ones.keys().all(|k| k % 2 == 1) && ones.len() == 128);
+        assert_well_formed(&ones);
+
+        // Everything below the root's crit bit agrees, so nothing is split.
+        let (zeros, ones) = high.split_by_bit(0);
+        assert!(zeros.is_empty());
+        assert_eq!(ones.len(), 128);
+        let (zeros, ones) = low.split_by_bit(8);
+        assert_eq!((zeros.len(), ones.len()), (128, 0));
+
+        let t: CritBit<i8, ()> = [(-5, ()), (3, ())].into_iter().collect();
+        let (non_negative, negative) = t.split_by_bit(0);
+        assert!(negative.keys().eq([-5].iter()));
+        assert!(non_negative.keys().eq([3].iter()));
+        let t: CritBit<i8, ()> = [(-5, ()), (-3, ())].into_iter().collect();
+        let (zeros, ones) = t.split_by_bit(0);
+        assert!(zeros.is_empty() && ones.len() == 2);
+        // The root branches on the sign bit, so its children are detached.
+        let t: CritBit<i8, ()> = (-128..=127i8).map(|k| (k, ())).collect();
+        let (zeros, ones) = t.split_by_bit(0);
+        assert_well_formed(&zeros);
+        assert_well_formed(&ones);
+        assert!(zeros.keys().copied().eq(0..=127));
+        assert!(ones.keys().copied().eq(-128..0));
+        let (a, b) = CritBit::<u8, ()>::new().split_by_bit(3);
+        assert!(a.is_empty() && b.is_empty());
+    }
+
+    #[test]
+    fn update() {
+        let mut t: CritBit<u32, Vec<u32>> = CritBit::new();
+        t.insert(4, vec![1]);
+        assert!(t.update(&4, |v| v.push(2)));
+        assert_eq!(t[&4], vec![1, 2]);
+        assert!(!t.update(&5, |_| unreachable!()));
+        assert_eq!(t.len(), 1);
+        assert!(!t.contains_key(&5));
+    }
+
+    #[test]
+    fn iter_mut_borrows_live_together() {
+        let mut t: CritBit<u16, u16> = (0..300u16).map(|i| (i.wrapping_mul(40_503), i)).collect();
+        let values: Vec<&mut u16> = t.values_mut().collect();
+        for v in values {
+            *v += 1;
+        }
+        assert!(t.values().all(|&v| (1..=300).contains(&v)));
+        assert_eq!(
+            t.values().map(|&v| v as u32).sum::<u32>(),
+            (1..=300u32).sum::<u32>()
+        );
+        assert_eq!(CritBit::<u8, u8>::new().iter_mut().next(), None);
     }
 }
@@ -0,0 +1,78 @@
+use num::PrimInt;
+use serde::de::{Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeSeq, Serializer};
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use crate::CritBit;
+
+// Trees travel as an ascending sequence of `(key, value)` pairs rather than as
+// their node layout, and are rebuilt through `insert` on the way back in.
+impl<K, V> Serialize for CritBit<K, V>
+where
+    K: PrimInt + Serialize,
+    V: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.len()))?;
+        for entry in self.iter() {
+            seq.serialize_element(&entry)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de, K, V> Deserialize<'de> for CritBit<K, V>
+where
+    K: PrimInt + Deserialize<'de>,
+    V: Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PairsVisitor<K, V>(PhantomData<(K, V)>);
+
+        impl<'de, K, V> Visitor<'de> for PairsVisitor<K, V>
+        where
+            K: PrimInt + Deserialize<'de>,
+            V: Deserialize<'de>,
+        {
+            type Value = CritBit<K, V>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a sequence of key-value pairs")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<CritBit<K, V>, A::Error> {
+                let mut t = CritBit::new();
+                while let Some((k, v)) = seq.next_element()? {
+                    t.insert(k, v);
+                }
+                Ok(t)
+            }
+        }
+
+        deserializer.deserialize_seq(PairsVisitor(PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::CritBit;
+
+    #[test]
+    fn json_round_trip() {
+        let t: CritBit<u32, String> = [(300u32, "c"), (7, "a"), (42, "b")]
+            .iter()
+            .map(|(k, v)| (*k, v.to_string()))
+            .collect();
+
+        let json = serde_json::to_string(&t).unwrap();
+        assert_eq!(json, r#"[[7,"a"],[42,"b"],[300,"c"]]"#);
+
+        let back: CritBit<u32, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, t);
+
+        let empty: CritBit<u32, String> = serde_json::from_str("[]").unwrap();
+        assert!(empty.is_empty());
+    }
+}
@@ -0,0 +1,145 @@
+use core::fmt::{self, Debug};
+
+use num::PrimInt;
+
+use crate::{CritBit, Difference, Intersection, Keys, Union};
+
+/// A set of integer keys. It's a map whose values carry nothing, so every
+/// operation here is the map's own, with the `()` values kept out of sight.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CritBitSet<K>
+where
+    K: PrimInt,
+{
+    map: CritBit<K, ()>,
+}
+
+impl<K: PrimInt> Default for CritBitSet<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: PrimInt> CritBitSet<K> {
+    pub const fn new() -> CritBitSet<K> {
+        CritBitSet {
+            map: CritBit::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Adds `key`, returning whether it was newly added.
+    pub fn insert(&mut self, key: K) -> bool {
+        self.map.insert_if_absent(key, ())
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.map.contains_key(key)
+    }
+
+    /// Takes `key` out, returning whether it was there.
+    pub fn remove(&mut self, key: &K) -> bool {
+        self.map.remove(key).is_some()
+    }
+
+    pub fn iter(&self) -> Keys<'_, K, ()> {
+        self.map.keys()
+    }
+
+    pub fn union<'a>(&'a self, other: &'a CritBitSet<K>) -> Union<'a, K, ()> {
+        Union::new(self.iter(), other.iter())
+    }
+
+    pub fn intersection<'a>(&'a self, other: &'a CritBitSet<K>) -> Intersection<'a, K, ()> {
+        Intersection::new(self.iter(), other.iter())
+    }
+
+    pub fn difference<'a>(&'a self, other: &'a CritBitSet<K>) -> Difference<'a, K, ()> {
+        Difference::new(self.iter(), other.iter())
+    }
+}
+
+impl<K: PrimInt + Debug> Debug for CritBitSet<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<K: PrimInt> FromIterator<K> for CritBitSet<K> {
+    fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> CritBitSet<K> {
+        let mut set = CritBitSet::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<K: PrimInt> Extend<K> for CritBitSet<K> {
+    fn extend<I: IntoIterator<Item = K>>(&mut self, iter: I) {
+        for k in iter {
+            self.insert(k);
+        }
+    }
+}
+
+impl<'a, K: PrimInt> IntoIterator for &'a CritBitSet<K> {
+    type Item = &'a K;
+    type IntoIter = Keys<'a, K, ()>;
+
+    fn into_iter(self) -> Keys<'a, K, ()> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::CritBitSet;
+
+    #[test]
+    fn insert_contains_remove() {
+        let mut s: CritBitSet<u8> = CritBitSet::new();
+        assert!(s.is_empty());
+        assert!(s.insert(7));
+        assert!(s.insert(200));
+        assert!(!s.insert(7));
+        assert_eq!(s.len(), 2);
+        assert!(s.contains(&7) && s.contains(&200));
+        assert!(!s.contains(&8));
+
+        assert!(s.remove(&7));
+        assert!(!s.remove(&7));
+        assert!(!s.contains(&7));
+        assert!(s.iter().copied().eq([200]));
+        assert_eq!(format!("{s:?}"), "{200}");
+    }
+
+    #[test]
+    fn set_operations() {
+        let a: CritBitSet<u8> = (0..=255u8).filter(|k| k % 2 == 0).collect();
+        let b: CritBitSet<u8> = (0..=255u8).filter(|k| k % 3 == 0).collect();
+        let expect =
+            |f: fn(bool, bool) -> bool| (0..=255u8).filter(move |k| f(k % 2 == 0, k % 3 == 0));
+
+        assert!(a.union(&b).copied().eq(expect(|x, y| x || y)));
+        assert!(a.intersection(&b).copied().eq(expect(|x, y| x && y)));
+        assert!(a.difference(&b).copied().eq(expect(|x, y| x && !y)));
+        assert!(b.difference(&a).copied().eq(expect(|x, y| !x && y)));
+
+        let empty = CritBitSet::new();
+        assert!(a.union(&empty).eq(a.iter()));
+        assert!(empty.union(&a).eq(a.iter()));
+        assert_eq!(a.intersection(&empty).next(), None);
+        assert!(a.difference(&empty).eq(a.iter()));
+        assert_eq!(empty.difference(&a).next(), None);
+    }
+}